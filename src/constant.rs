@@ -1,2 +1,8 @@
-/// the max end time
-pub const MAX_UNTIL_STR: &str = "23000101T000000Z";
\ No newline at end of file
+/// the max year of the expansion, a rule without count and until stops here
+pub const MAX_YEAR: i32 = 9999;
+
+/// the max length of the list returned by all
+pub const MAX_ALL_COUNT: usize = 65535;
+
+/// the max number of periods expanded by the rules of a set, like the days of `FREQ=DAILY`
+pub const MAX_ITERATIONS: usize = 10_000_000;
@@ -0,0 +1,53 @@
+use std::fmt;
+
+/// The error returned when a rule, date or timezone can not be parsed
+#[derive(PartialEq, Debug, Clone)]
+pub enum RRuleError {
+    /// Unknown or malformed property, like `FOO=1` or `FREQ`
+    InvalidProperty(String),
+    /// The value of a property is invalid, the first member is the property name
+    InvalidValue(String, String),
+    /// The timezone is not a valid IANA timezone
+    InvalidTimezone(String),
+    /// The date or date-time is malformed or does not exist
+    InvalidDate(String),
+    /// The properties are valid alone but not together, like `COUNT` with `UNTIL`
+    InvalidRule(String),
+    /// The feature is valid in RFC 5545 or its extensions but not supported yet
+    Unsupported(String),
+}
+
+impl RRuleError {
+    pub(crate) fn invalid_value(property: &str, value: &str) -> Self {
+        Self::InvalidValue(property.to_string(), value.to_string())
+    }
+
+    /// The code of the error, used as the `code` of the js Error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidProperty(_) => "INVALID_PROPERTY",
+            Self::InvalidValue(_, _) => "INVALID_VALUE",
+            Self::InvalidTimezone(_) => "INVALID_TIMEZONE",
+            Self::InvalidDate(_) => "INVALID_DATE",
+            Self::InvalidRule(_) => "INVALID_RULE",
+            Self::Unsupported(_) => "UNSUPPORTED",
+        }
+    }
+}
+
+impl fmt::Display for RRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidProperty(property) => write!(f, "invalid property: {}", property),
+            Self::InvalidValue(property, value) => {
+                write!(f, "invalid value of {}: {}", property, value)
+            }
+            Self::InvalidTimezone(tz) => write!(f, "invalid timezone: {}", tz),
+            Self::InvalidDate(date) => write!(f, "invalid date: {}", date),
+            Self::InvalidRule(message) => write!(f, "invalid rule: {}", message),
+            Self::Unsupported(feature) => write!(f, "unsupported: {}", feature),
+        }
+    }
+}
+
+impl std::error::Error for RRuleError {}
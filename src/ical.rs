@@ -0,0 +1,504 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, TimeZone};
+use chrono_tz::Tz;
+
+use crate::error::RRuleError;
+use crate::point_time::{PointTime, TimeForm};
+use crate::rrule::unfold_lines;
+use crate::rrule_set::RRuleSet;
+use crate::timezone::{get_timezone, Timezone};
+
+mod vtimezone;
+pub use vtimezone::parse_vtimezone;
+
+/// 一行内容，如 DTSTART;TZID=Asia/Shanghai:20231023T090000
+#[derive(PartialEq, Debug)]
+pub struct ContentLine {
+    /// 名称，已转为大写
+    pub name: String,
+    /// 参数，名称已转为大写，值已去掉双引号
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl FromStr for ContentLine {
+    type Err = RRuleError;
+    /// 参数值可以用双引号包起来，其中的 `:`、`;` 不是分隔符
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<String> = vec![];
+        let mut current = String::new();
+        let mut in_quote = false;
+        let mut value_start: Option<usize> = None;
+        for (i, c) in line.char_indices() {
+            match c {
+                '"' => in_quote = !in_quote,
+                ';' if !in_quote => parts.push(std::mem::take(&mut current)),
+                ':' if !in_quote => {
+                    parts.push(std::mem::take(&mut current));
+                    value_start = Some(i + 1);
+                    break;
+                }
+                _ => current.push(c),
+            }
+        }
+        let value_start = value_start.ok_or_else(|| RRuleError::InvalidProperty(line.to_string()))?;
+        let name = parts[0].trim().to_uppercase();
+        if name.is_empty() {
+            return Err(RRuleError::InvalidProperty(line.to_string()));
+        }
+        let params = parts[1..]
+            .iter()
+            .map(|param| match param.split_once('=') {
+                Some((key, value)) => Ok((key.trim().to_uppercase(), value.to_string())),
+                None => Err(RRuleError::InvalidProperty(param.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ContentLine {
+            name,
+            params,
+            value: line[value_start..].to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ContentLine {
+    /// 参数值包含 `:`、`;`、`,` 时加上双引号
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (key, value) in self.params.iter() {
+            if value.contains([':', ';', ',']) {
+                write!(f, ";{}=\"{}\"", key, value)?;
+            } else {
+                write!(f, ";{}={}", key, value)?;
+            }
+        }
+        write!(f, ":{}", self.value)
+    }
+}
+
+impl ContentLine {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| &value[..])
+    }
+
+    /// 转为RRuleSet能解析的行，去掉参数值的双引号
+    fn to_set_line(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|(key, value)| format!(";{}={}", key, value))
+            .collect::<String>();
+        format!("{}{}:{}", self.name, params, self.value)
+    }
+
+    /// 解析DATE或DATE-TIME的值，带Z的是UTC时间，有TZID时使用TZID，否则使用tz
+    fn to_date_time(
+        &self,
+        tz: &Timezone,
+        timezones: &[Timezone],
+    ) -> Result<DateTime<Timezone>, RRuleError> {
+        let point_time = self.value.parse::<PointTime>()?;
+        let tz = if self.value.ends_with('Z') {
+            Timezone::UTC
+        } else {
+            match self.param("TZID") {
+                Some(tzid) => get_timezone(tzid, timezones)?,
+                None => tz.clone(),
+            }
+        };
+        let time = point_time
+            .to_naive_date_time()
+            .ok_or_else(|| RRuleError::InvalidDate(self.value.clone()))?;
+        tz.from_local_datetime(&time)
+            .earliest()
+            .ok_or_else(|| RRuleError::InvalidDate(self.value.clone()))
+    }
+}
+
+/// VEVENT中的一个重复事件
+#[derive(Debug)]
+pub struct VEvent {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    /// 由DTSTART、RRULE、EXRULE、RDATE、EXDATE生成
+    pub rrule_set: RRuleSet,
+    pub dt_end: Option<DateTime<Timezone>>,
+    pub duration: Option<Duration>,
+    /// 修改某一次的时间时，被修改的那一次的原始时间
+    pub recurrence_id: Option<DateTime<Timezone>>,
+    /// 同一UID下带RECURRENCE-ID的VEVENT
+    pub overrides: Vec<VEvent>,
+}
+
+impl VEvent {
+    /// 返回所有的时间，被修改的那一次使用修改后的开始时间
+    /// 按RFC 5545，DTSTART总是第一次，除非被EXDATE排除
+    pub fn all(&self) -> Vec<DateTime<Timezone>> {
+        let recurrence_ids = self
+            .overrides
+            .iter()
+            .filter_map(|n| n.recurrence_id.clone())
+            .collect::<Vec<_>>();
+        let set = &self.rrule_set;
+        let mut list = set.all().list;
+        if let Some(start) = &set.start_point_time {
+            if !set.exdate.contains(start) {
+                list.extend(start.with_timezone_policy(&set.tz, &set.dst_policy));
+            }
+        }
+        list.retain(|n| !recurrence_ids.contains(n));
+        for event in self.overrides.iter() {
+            let set = &event.rrule_set;
+            if let Some(start) = &set.start_point_time {
+                list.extend(start.with_timezone_policy(&set.tz, &set.dst_policy));
+            }
+        }
+        list.sort();
+        list.dedup();
+        list
+    }
+
+    /// 解析一个VEVENT中的行，不包含BEGIN和END，嵌套的组件如VALARM被忽略
+    fn from_lines(lines: &[ContentLine], timezones: &[Timezone]) -> Result<VEvent, RRuleError> {
+        let mut set_lines: Vec<String> = vec![];
+        let mut uid: Option<String> = None;
+        let mut summary: Option<String> = None;
+        let mut tz = Timezone::UTC;
+        let mut duration: Option<Duration> = None;
+        let mut dt_end_line: Option<&ContentLine> = None;
+        let mut recurrence_id_line: Option<&ContentLine> = None;
+        // 嵌套的组件层数
+        let mut depth = 0;
+        for line in lines.iter() {
+            match &line.name[..] {
+                "BEGIN" => depth += 1,
+                "END" => depth -= 1,
+                _ if depth > 0 => {}
+                "DTSTART" => {
+                    let start = line.value.parse::<PointTime>()?;
+                    match line.param("TZID") {
+                        Some(tzid) if !line.value.ends_with('Z') => {
+                            tz = get_timezone(tzid, timezones)?;
+                            set_lines.push(format!("DTSTART;TZID={}:{}", tzid, start));
+                        }
+                        _ => set_lines.push(format!("DTSTART:{}", start)),
+                    }
+                }
+                "RRULE" | "EXRULE" | "RDATE" | "EXDATE" => set_lines.push(line.to_set_line()),
+                "UID" => uid = Some(unescape_text(&line.value)),
+                "SUMMARY" => summary = Some(unescape_text(&line.value)),
+                "DTEND" => dt_end_line = Some(line),
+                "DURATION" => duration = Some(parse_duration(&line.value)?),
+                "RECURRENCE-ID" => recurrence_id_line = Some(line),
+                _ => {}
+            }
+        }
+        Ok(VEvent {
+            uid,
+            summary,
+            rrule_set: RRuleSet::from_str_with_timezones(&set_lines.join("\n"), timezones)?,
+            dt_end: dt_end_line
+                .map(|n| n.to_date_time(&tz, timezones))
+                .transpose()?,
+            duration,
+            recurrence_id: recurrence_id_line
+                .map(|n| n.to_date_time(&tz, timezones))
+                .transpose()?,
+            overrides: vec![],
+        })
+    }
+}
+
+/// 解析VCALENDAR，每个VEVENT生成一个VEvent
+/// - 先按RFC 5545展开折叠的行
+/// - TZID不是IANA时区时，使用同名的VTIMEZONE生成的时区
+/// - 带RECURRENCE-ID的VEVENT放到同一UID的VEvent的overrides中，找不到时单独返回
+/// - VEVENT中嵌套的组件如VALARM被忽略
+pub fn parse_vcalendar(s: &str) -> Result<Vec<VEvent>, RRuleError> {
+    let mut events: Vec<VEvent> = vec![];
+    let mut overrides: Vec<VEvent> = vec![];
+    let mut timezones: Vec<Timezone> = vec![];
+    let mut event_blocks: Vec<Vec<ContentLine>> = vec![];
+    // 当前组件的名称和其中的行，包括嵌套的组件
+    let mut block: Option<(String, Vec<ContentLine>)> = None;
+    let mut depth = 0;
+    for line in unfold_lines(s).lines().filter(|n| !n.trim().is_empty()) {
+        let line: ContentLine = line.parse()?;
+        let value = line.value.trim().to_uppercase();
+        match (&line.name[..], &mut block) {
+            ("BEGIN", None) if value == "VEVENT" || value == "VTIMEZONE" => {
+                block = Some((value, vec![]));
+            }
+            ("END", Some((name, _))) if depth == 0 && value == *name => {
+                let (name, lines) = block.take().unwrap_or_default();
+                if name == "VTIMEZONE" {
+                    timezones.push(vtimezone::from_lines(&lines)?.into());
+                } else {
+                    event_blocks.push(lines);
+                }
+            }
+            (_, Some((_, lines))) => {
+                match &line.name[..] {
+                    "BEGIN" => depth += 1,
+                    "END" => depth -= 1,
+                    _ => {}
+                }
+                lines.push(line);
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, _)) = block {
+        return Err(RRuleError::InvalidRule(format!("{} is not closed", name)));
+    }
+    for lines in event_blocks.iter() {
+        let event = VEvent::from_lines(lines, &timezones)?;
+        if event.recurrence_id.is_some() {
+            overrides.push(event);
+        } else {
+            events.push(event);
+        }
+    }
+    for event in overrides {
+        match events
+            .iter_mut()
+            .find(|n| n.uid.is_some() && n.uid == event.uid)
+        {
+            Some(master) => master.overrides.push(event),
+            None => events.push(event),
+        }
+    }
+    Ok(events)
+}
+
+/// 导出VEVENT时由调用方提供的属性
+#[derive(Debug, Default, Clone)]
+pub struct EventProps {
+    pub uid: String,
+    pub summary: Option<String>,
+    /// 结束时间，使用RRuleSet的时区
+    pub dt_end: Option<PointTime>,
+    /// 创建时间，UTC时间，RFC 5545要求必须有，没有时使用DTSTART
+    pub dt_stamp: Option<PointTime>,
+}
+
+/// 导出VCALENDAR的PRODID
+pub const PRODID: &str = "-//rrule-rust//rrule-rust//EN";
+
+/// 导出包含一个VEVENT的VCALENDAR
+/// - 使用CRLF换行，超过75个字节的行按RFC 5545折叠
+/// - 时区不是UTC时，DTSTART、DTEND、RDATE、EXDATE带TZID，时间不带Z，并带上对应的VTIMEZONE
+pub fn to_vcalendar(set: &RRuleSet, props: &EventProps) -> String {
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODID),
+    ];
+    lines.extend(vtimezone::vtimezone_lines(set));
+    lines.push("BEGIN:VEVENT".to_string());
+    lines.push(format!("UID:{}", escape_text(&props.uid)));
+    let dt_stamp = match (&props.dt_stamp, &set.start_point_time) {
+        (Some(time), _) => Some(time.with_form(TimeForm::Utc)),
+        (None, Some(start)) => Some(
+            start
+                .convert_timezone(&set.tz, &Tz::UTC)
+                .with_form(TimeForm::Utc),
+        ),
+        (None, None) => None,
+    };
+    if let Some(time) = dt_stamp {
+        lines.push(format!("DTSTAMP:{}", time));
+    }
+    if let Some(start) = &set.start_point_time {
+        lines.extend(RRuleSet::date_list_lines("DTSTART", &[start], &set.tz));
+    }
+    if let Some(end) = &props.dt_end {
+        lines.extend(RRuleSet::date_list_lines("DTEND", &[end], &set.tz));
+    }
+    if let Some(summary) = &props.summary {
+        lines.push(format!("SUMMARY:{}", escape_text(summary)));
+    }
+    lines.extend(
+        set.rrule
+            .iter()
+            .map(|n| format!("RRULE:{}", set.rrule_for_output(n))),
+    );
+    lines.extend(
+        set.exrule
+            .iter()
+            .map(|n| format!("EXRULE:{}", set.rrule_for_output(n))),
+    );
+    for (name, dates) in [("RDATE", &set.rdate), ("EXDATE", &set.exdate)] {
+        lines.extend(RRuleSet::date_list_lines(name, &dates.iter().collect::<Vec<_>>(), &set.tz));
+    }
+    lines.extend(set.other_lines.iter().cloned());
+    lines.push("END:VEVENT".to_string());
+    lines.push("END:VCALENDAR".to_string());
+    to_content(&lines)
+}
+
+/// 根据RRuleSet的时区生成VTIMEZONE，覆盖序列涉及的年份，UTC时返回空字符串
+/// 包含STANDARD和DAYLIGHT组件，每年按同一规则的切换用RRULE表示
+pub fn to_vtimezone(set: &RRuleSet) -> String {
+    to_content(&vtimezone::vtimezone_lines(set))
+}
+
+/// 折叠每一行，并以CRLF结尾
+fn to_content(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| format!("{}\r\n", fold_line(line)))
+        .collect()
+}
+
+/// 按RFC 5545折叠长行，每行不超过75个字节，续行以空格开头，不会拆开多字节字符
+pub fn fold_line(line: &str) -> String {
+    let mut result = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            result.push_str("\r\n ");
+            width = 1;
+        }
+        result.push(c);
+        width += c.len_utf8();
+    }
+    result
+}
+
+/// 转义TEXT，与unescape_text相反
+pub fn escape_text(s: &str) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\n' => result.push_str("\\n"),
+            '\r' => {}
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// 去掉TEXT的转义，如 `\,`、`\;`、`\\`、`\n`
+pub fn unescape_text(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// 解析DURATION，如 `P1W`、`PT1H30M`、`-P1DT2H`
+pub fn parse_duration(s: &str) -> Result<Duration, RRuleError> {
+    let invalid = || RRuleError::invalid_value("DURATION", s);
+    let (negative, rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    let mut has_unit = false;
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' && number.is_empty() {
+            in_time = true;
+            continue;
+        }
+        let n = number.parse::<i64>().map_err(|_| invalid())?;
+        duration = duration
+            + match (c, in_time) {
+                ('W', false) => Duration::weeks(n),
+                ('D', false) => Duration::days(n),
+                ('H', true) => Duration::hours(n),
+                ('M', true) => Duration::minutes(n),
+                ('S', true) => Duration::seconds(n),
+                _ => return Err(invalid()),
+            };
+        number.clear();
+        has_unit = true;
+    }
+    if !number.is_empty() || !has_unit {
+        return Err(invalid());
+    }
+    Ok(if negative { -duration } else { duration })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_content_line() {
+        let line: ContentLine = "DTSTART;TZID=\"America/New_York\";x-param=\"a:b;c\":20231023T090000"
+            .parse()
+            .unwrap();
+        assert_eq!(line.name, "DTSTART");
+        assert_eq!(line.param("TZID"), Some("America/New_York"));
+        assert_eq!(line.param("X-PARAM"), Some("a:b;c"));
+        assert_eq!(line.value, "20231023T090000");
+
+        let line: ContentLine = "DESCRIPTION:a:b".parse().unwrap();
+        assert_eq!(line.value, "a:b");
+        assert!("DESCRIPTION".parse::<ContentLine>().is_err());
+    }
+
+    #[test]
+    fn test_unescape_text() {
+        assert_eq!(unescape_text("a\\, b\\; c\\\\d\\ne"), "a, b; c\\d\ne");
+    }
+
+    #[test]
+    fn test_escape_text() {
+        let text = "a, b; c\\d\ne";
+        assert_eq!(escape_text(text), "a\\, b\\; c\\\\d\\ne");
+        assert_eq!(unescape_text(&escape_text(text)), text);
+    }
+
+    #[test]
+    fn test_fold_line() {
+        assert_eq!(fold_line("SUMMARY:short"), "SUMMARY:short");
+        let line = format!("SUMMARY:{}", "a".repeat(100));
+        let folded = fold_line(&line);
+        assert!(folded.split("\r\n").all(|n| n.len() <= 75));
+        assert_eq!(unfold_lines(&folded), line);
+
+        let line = format!("SUMMARY:{}", "会议".repeat(30));
+        let folded = fold_line(&line);
+        assert!(folded.split("\r\n").all(|n| n.len() <= 75));
+        assert_eq!(unfold_lines(&folded), line);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT1H30M"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1W"), Ok(Duration::weeks(1)));
+        assert_eq!(parse_duration("-P1DT2H"), Ok(-Duration::hours(26)));
+        assert_eq!(parse_duration("P15DT5H0M20S"), Ok(Duration::seconds(15 * 86400 + 5 * 3600 + 20)));
+        for s in ["P", "PT", "1H", "P1M", "PT1D", "P1H"] {
+            assert!(parse_duration(s).is_err(), "{}", s);
+        }
+    }
+}
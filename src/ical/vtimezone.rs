@@ -0,0 +1,416 @@
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+
+use super::ContentLine;
+use crate::error::RRuleError;
+use crate::point_time::{PointTime, TimeForm};
+use crate::rrule::weekday::NWeekday;
+use crate::rrule::RRule;
+use crate::rrule_set::{RRuleIter, RRuleSet};
+use crate::timezone::{CustomTimezone, OffsetChange, RecurringTransition, Timezone};
+use std::str::FromStr;
+
+/// 某一时刻使用的偏移
+#[derive(PartialEq, Debug, Clone)]
+struct Observance {
+    /// 相对UTC的偏移，单位为秒
+    offset: i32,
+    dst: bool,
+    name: String,
+}
+
+impl Observance {
+    fn at(tz: &Tz, utc: &NaiveDateTime) -> Observance {
+        let offset = tz.offset_from_utc_datetime(utc);
+        Observance {
+            offset: offset.fix().local_minus_utc(),
+            dst: offset.dst_offset().num_seconds() != 0,
+            name: offset.abbreviation().to_string(),
+        }
+    }
+}
+
+/// 时区的一次切换
+#[derive(Debug)]
+struct Transition {
+    /// 切换时刻的当地时间，按切换前的偏移计算
+    local: NaiveDateTime,
+    utc: NaiveDateTime,
+    from: i32,
+    to: Observance,
+}
+
+impl Transition {
+    /// 每年切换的规则，如3月的第2个星期日为 (3, 2SU)，最后一个星期日为 (3, -1SU)
+    fn yearly_rule(&self) -> (u32, NWeekday) {
+        let date = self.local.date();
+        let next_month = match date.month() {
+            12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+            month => NaiveDate::from_ymd_opt(date.year(), month + 1, 1),
+        };
+        let is_last = next_month.is_none_or(|n| date + Duration::days(7) >= n);
+        let nth = if is_last { -1 } else { (date.day() as i16 - 1) / 7 + 1 };
+        (date.month(), NWeekday::new(Some(nth), date.weekday()))
+    }
+
+    /// 能否和前一年的切换合并为一条规则
+    fn follows(&self, prev: &Transition) -> bool {
+        self.local.year() == prev.local.year() + 1
+            && self.from == prev.from
+            && self.to == prev.to
+            && self.local.time() == prev.local.time()
+            && self.yearly_rule() == prev.yearly_rule()
+    }
+}
+
+/// 找出 [start_year, end_year] 中所有的切换
+/// 每次前进3天检查偏移的变化，有变化时在这3天内按秒二分查找第一次切换的时刻，再从切换处继续检查
+/// 3天内切换后又切换回原来的偏移时无法识别，时区数据中这样的间隔最短约为7天，如巴西2000年的夏令时
+fn find_transitions(tz: &Tz, start_year: i32, end_year: i32) -> (Observance, Vec<Transition>) {
+    let to_time = |year: i32| {
+        NaiveDate::from_ymd_opt(year, 1, 1)
+            .and_then(|n| n.and_hms_opt(0, 0, 0))
+            .unwrap()
+    };
+    let mut time = to_time(start_year);
+    let end = to_time(end_year + 1);
+    let first = Observance::at(tz, &time);
+    let mut current = first.clone();
+    let mut list = vec![];
+    while time < end {
+        let next = (time + Duration::days(3)).min(end);
+        if Observance::at(tz, &next) == current {
+            time = next;
+            continue;
+        }
+        let (mut low, mut high) = (0, (next - time).num_seconds());
+        while low < high {
+            let mid = (low + high) / 2;
+            if Observance::at(tz, &(time + Duration::seconds(mid))) == current {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let utc = time + Duration::seconds(low);
+        let observance = Observance::at(tz, &utc);
+        list.push(Transition {
+            local: utc + Duration::seconds(current.offset as i64),
+            utc,
+            from: current.offset,
+            to: observance.clone(),
+        });
+        current = observance;
+        time = utc;
+    }
+    (first, list)
+}
+
+/// 序列涉及的年份，没有结束的序列至少包含两年，使最后的切换规则能够生成RRULE
+/// 有UNTIL的规则按UNTIL取年份，只有COUNT的规则逐个展开到最后一个时间，不生成整个列表
+fn year_range(set: &RRuleSet) -> Option<(i32, i32)> {
+    let start = set
+        .start_point_time
+        .iter()
+        .chain(set.rdate.iter())
+        .map(|n| n.year)
+        .min()?;
+    let mut end = set.rdate.iter().map(|n| n.year).max().unwrap_or(start);
+    let bounded = set.rrule.iter().all(|n| n.count > 0 || n.until.is_some());
+    if !bounded {
+        return Some((start, end.max(start + 1)));
+    }
+    for rrule in set.rrule.iter() {
+        let last = match (&rrule.until, &set.start_point_time) {
+            // UTC写法的UNTIL按tz下的当地时间取年份
+            (Some(until), _) if until.form == TimeForm::Utc => {
+                Some(until.convert_timezone(&Timezone::UTC, &set.tz).year)
+            }
+            (Some(until), _) => Some(until.year),
+            (None, Some(dt_start)) => RRuleIter::new_in_timezone(rrule, dt_start, &set.tz)
+                .last()
+                .map(|n| n.year),
+            (None, None) => None,
+        };
+        end = end.max(last.unwrap_or(start));
+    }
+    Some((start, end))
+}
+
+/// 格式化偏移，如 `+0800`、`-0430`，有秒时为 `+053328`
+fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    let (hour, min, sec) = (offset / 3600, offset / 60 % 60, offset % 60);
+    if sec == 0 {
+        format!("{}{:02}{:02}", sign, hour, min)
+    } else {
+        format!("{}{:02}{:02}{:02}", sign, hour, min, sec)
+    }
+}
+
+fn observance_lines(
+    start: &NaiveDateTime,
+    from: i32,
+    to: &Observance,
+    rrule: Option<String>,
+) -> Vec<String> {
+    let kind = if to.dst { "DAYLIGHT" } else { "STANDARD" };
+    let mut lines = vec![
+        format!("BEGIN:{}", kind),
+        format!("DTSTART:{}", PointTime::from_naive_date_time(start)),
+        format!("TZOFFSETFROM:{}", format_offset(from)),
+        format!("TZOFFSETTO:{}", format_offset(to.offset)),
+        format!("TZNAME:{}", to.name),
+    ];
+    lines.extend(rrule.map(|n| format!("RRULE:{}", n)));
+    lines.push(format!("END:{}", kind));
+    lines
+}
+
+/// 生成RRuleSet的时区的VTIMEZONE，UTC或者没有开始时间时返回空
+/// - 自定义时区原样输出解析时的VTIMEZONE
+/// - 第一个组件为序列开始那一年1月1日使用的偏移
+/// - 连续多年按同一规则的切换合并为一个带RRULE的组件，规则在序列结束前停止时带UNTIL
+pub(crate) fn vtimezone_lines(set: &RRuleSet) -> Vec<String> {
+    let tz = match &set.tz {
+        Timezone::Tz(tz) if *tz != Tz::UTC => tz,
+        Timezone::Custom(tz) => return tz.lines.clone(),
+        _ => return vec![],
+    };
+    let (start_year, end_year) = match year_range(set) {
+        Some(range) => range,
+        None => return vec![],
+    };
+    let (first, transitions) = find_transitions(tz, start_year, end_year);
+    let mut lines = vec![
+        "BEGIN:VTIMEZONE".to_string(),
+        format!("TZID:{}", tz.name()),
+    ];
+    let start = NaiveDate::from_ymd_opt(start_year, 1, 1)
+        .and_then(|n| n.and_hms_opt(0, 0, 0))
+        .unwrap();
+    lines.extend(observance_lines(&start, first.offset, &first, None));
+
+    // 每组为连续多年按同一规则的切换
+    let mut groups: Vec<Vec<&Transition>> = vec![];
+    for transition in transitions.iter() {
+        let group = groups
+            .iter_mut()
+            .find(|n| n.last().is_some_and(|last| transition.follows(last)));
+        match group {
+            Some(group) => group.push(transition),
+            None => groups.push(vec![transition]),
+        }
+    }
+    for group in groups.iter() {
+        let (head, last) = (group[0], group[group.len() - 1]);
+        let rrule = if group.len() < 2 {
+            None
+        } else {
+            let (month, weekday) = head.yearly_rule();
+            let until = if last.local.year() < end_year {
+                format!(";UNTIL={}", PointTime::from_naive_date_time(&last.utc).with_form(TimeForm::Utc))
+            } else {
+                "".to_string()
+            };
+            Some(format!("FREQ=YEARLY;BYMONTH={};BYDAY={}{}", month, weekday, until))
+        };
+        lines.extend(observance_lines(&head.local, head.from, &head.to, rrule));
+    }
+    lines.push("END:VTIMEZONE".to_string());
+    lines
+}
+
+/// 解析 `+0100`、`-0500`、`+053328` 格式的偏移，不能达到24小时
+fn parse_offset(name: &str, value: &str) -> Result<FixedOffset, RRuleError> {
+    let invalid = || RRuleError::invalid_value(name, value);
+    let sign = match value.get(..1) {
+        Some("+") => 1,
+        Some("-") => -1,
+        _ => return Err(invalid()),
+    };
+    let digits = &value[1..];
+    if !(digits.len() == 4 || digits.len() == 6) || !digits.bytes().all(|n| n.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let part = |i: usize| digits.get(i..i + 2).map_or(0, |n| n.parse::<i32>().unwrap_or(0));
+    let (hour, min, sec) = (part(0), part(2), part(4));
+    if min > 59 || sec > 59 {
+        return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hour * 3600 + min * 60 + sec)).ok_or_else(invalid)
+}
+
+/// VTIMEZONE中的一个STANDARD或DAYLIGHT
+#[derive(Default)]
+struct ObservanceDef {
+    start: Option<PointTime>,
+    offset_from: Option<FixedOffset>,
+    offset_to: Option<FixedOffset>,
+    rrule: Option<RRule>,
+    rdate: Vec<PointTime>,
+}
+
+impl ObservanceDef {
+    /// 切换前的偏移、所有有限的切换的UTC时间和切换后的偏移，以及没有结束的规则
+    /// - DTSTART总是第一次切换，作为RDATE与RRULE一起展开，重复的时间只保留一个
+    /// - 没有COUNT和UNTIL的规则不在这里展开，由CustomTimezone按年展开
+    fn transitions(
+        self,
+        kind: &str,
+    ) -> Result<(FixedOffset, Vec<OffsetChange>, Option<RecurringTransition>), RRuleError> {
+        let missing = |name: &str| RRuleError::InvalidRule(format!("{} is required in {}", name, kind));
+        let start = self.start.ok_or_else(|| missing("DTSTART"))?;
+        let offset_from = self.offset_from.ok_or_else(|| missing("TZOFFSETFROM"))?;
+        let offset_to = self.offset_to.ok_or_else(|| missing("TZOFFSETTO"))?;
+        let mut set = RRuleSet::from_str("")?;
+        set.start_point_time = Some(start.clone());
+        set.rdate = self.rdate;
+        set.rdate.push(start.clone());
+        let recurring = match self.rrule {
+            Some(rrule) if rrule.count == 0 && rrule.until.is_none() => Some(RecurringTransition {
+                rrule,
+                start: start.to_naive_date_time().ok_or_else(|| RRuleError::InvalidDate(start.to_string()))?,
+                offset_from,
+                offset_to,
+            }),
+            rrule => {
+                set.rrule.extend(rrule);
+                None
+            }
+        };
+        // 开始时间是按切换前的偏移的当地时间
+        let list = set
+            .iter()
+            .map(|n| (n.naive_local() - Duration::seconds(offset_from.local_minus_utc() as i64), offset_to))
+            .collect();
+        Ok((offset_from, list, recurring))
+    }
+}
+
+/// 解析VTIMEZONE中的行，不包含BEGIN:VTIMEZONE和END:VTIMEZONE
+/// - 每个STANDARD和DAYLIGHT的DTSTART、RRULE、RDATE都是一次切换，切换前使用TZOFFSETFROM，之后使用TZOFFSETTO
+/// - RRULE的UNTIL是UTC时间，转换为当地时间后再展开
+/// - 第一次切换之前使用第一次切换的TZOFFSETFROM
+pub(crate) fn from_lines(lines: &[ContentLine]) -> Result<CustomTimezone, RRuleError> {
+    let mut tzid: Option<String> = None;
+    let mut observances: Vec<(String, ObservanceDef)> = vec![];
+    let mut current: Option<(String, ObservanceDef)> = None;
+    for line in lines.iter() {
+        let value = line.value.trim();
+        match (&line.name[..], &mut current) {
+            ("TZID", None) => tzid = Some(value.to_string()),
+            ("BEGIN", None) => current = Some((value.to_uppercase(), ObservanceDef::default())),
+            ("END", Some(_)) => observances.extend(current.take()),
+            ("DTSTART", Some((_, def))) => def.start = Some(value.parse()?),
+            ("TZOFFSETFROM", Some((_, def))) => def.offset_from = Some(parse_offset("TZOFFSETFROM", value)?),
+            ("TZOFFSETTO", Some((_, def))) => def.offset_to = Some(parse_offset("TZOFFSETTO", value)?),
+            ("RRULE", Some((_, def))) => def.rrule = Some(RRule::from_str(value)?),
+            ("RDATE", Some((_, def))) => {
+                let (list, _) = crate::rrule::parse_date_list_str_and_tz(&line.to_set_line(), &[])?;
+                def.rdate.extend(list);
+            }
+            _ => {}
+        }
+    }
+    let tzid = tzid.ok_or_else(|| RRuleError::InvalidRule("TZID is required in VTIMEZONE".to_string()))?;
+    let mut initial: Option<(NaiveDateTime, FixedOffset)> = None;
+    let mut transitions = vec![];
+    let mut recurring = vec![];
+    for (kind, mut def) in observances {
+        if let (Some(rrule), Some(offset_from)) = (&mut def.rrule, def.offset_from) {
+            if let Some(until) = rrule.until.as_ref().and_then(|n| n.to_naive_date_time()) {
+                let local = until + Duration::seconds(offset_from.local_minus_utc() as i64);
+                rrule.until = Some(PointTime::from_naive_date_time(&local));
+            }
+        }
+        let (offset_from, list, rule) = def.transitions(&kind)?;
+        if let Some((first, _)) = list.first() {
+            if initial.is_none_or(|(time, _)| *first < time) {
+                initial = Some((*first, offset_from));
+            }
+        }
+        transitions.extend(list);
+        recurring.extend(rule);
+    }
+    let (_, initial_offset) = initial.ok_or_else(|| {
+        RRuleError::InvalidRule("STANDARD or DAYLIGHT is required in VTIMEZONE".to_string())
+    })?;
+    let mut raw = vec!["BEGIN:VTIMEZONE".to_string()];
+    raw.extend(lines.iter().map(|n| n.to_string()));
+    raw.push("END:VTIMEZONE".to_string());
+    Ok(CustomTimezone::new(&tzid, initial_offset, transitions, recurring, raw))
+}
+
+/// 解析一个VTIMEZONE，生成的时区可以用于 `RRuleSet::set_timezone` 和 `RRuleSet::from_str_with_timezones`
+pub fn parse_vtimezone(s: &str) -> Result<CustomTimezone, RRuleError> {
+    let lines = crate::rrule::unfold_lines(s)
+        .lines()
+        .filter(|n| !n.trim().is_empty())
+        .map(|n| n.parse::<ContentLine>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let is_component = |line: Option<&ContentLine>, name: &str| {
+        line.is_some_and(|n| n.name == name && n.value.trim().eq_ignore_ascii_case("VTIMEZONE"))
+    };
+    if !is_component(lines.first(), "BEGIN") || !is_component(lines.last(), "END") {
+        return Err(RRuleError::InvalidRule("VTIMEZONE is not closed".to_string()));
+    }
+    from_lines(&lines[1..lines.len() - 1])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_transitions() {
+        let (first, list) = find_transitions(&Tz::America__New_York, 2023, 2023);
+        assert_eq!((first.offset, first.dst, &first.name[..]), (-5 * 3600, false, "EST"));
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].local.to_string(), "2023-03-12 02:00:00");
+        assert_eq!(list[0].utc.to_string(), "2023-03-12 07:00:00");
+        assert_eq!(list[0].yearly_rule(), (3, NWeekday::new(Some(2), chrono::Weekday::Sun)));
+        assert_eq!(list[1].local.to_string(), "2023-11-05 02:00:00");
+        assert_eq!(list[1].yearly_rule(), (11, NWeekday::new(Some(1), chrono::Weekday::Sun)));
+
+        let (_, list) = find_transitions(&Tz::Europe__London, 2023, 2023);
+        assert_eq!(list[0].yearly_rule(), (3, NWeekday::new(Some(-1), chrono::Weekday::Sun)));
+        assert_eq!(list[1].local.to_string(), "2023-10-29 02:00:00");
+
+        assert!(find_transitions(&Tz::Asia__Shanghai, 2023, 2030).1.is_empty());
+
+        // 只持续一周的夏令时，以及同一天内的两次切换
+        let (_, list) = find_transitions(&Tz::America__Noronha, 2000, 2000);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[1].utc.to_string(), "2000-10-08 02:00:00");
+        assert_eq!(list[2].utc.to_string(), "2000-10-15 01:00:00");
+        let (_, list) = find_transitions(&Tz::Atlantic__Azores, 1942, 1942);
+        assert_eq!(list[1].utc.to_string(), "1942-04-25 23:00:00");
+        assert_eq!((list[1].to.offset, list[2].to.offset), (-3600, 0));
+        assert_eq!(list[2].utc.to_string(), "1942-04-26 00:00:00");
+    }
+
+    #[test]
+    fn test_year_range() {
+        let range = |s: &str| year_range(&RRuleSet::from_str(s).unwrap());
+        assert_eq!(range("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY"), Some((2023, 2024)));
+        assert_eq!(range("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;COUNT=100000"), Some((2023, 2297)));
+        assert_eq!(range("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;UNTIL=21000101T000000"), Some((2023, 2100)));
+        // UTC写法的UNTIL按当地时间取年份
+        assert_eq!(
+            range("DTSTART;TZID=Asia/Shanghai:20231023T090000\nRRULE:FREQ=DAILY;UNTIL=20231231T200000Z"),
+            Some((2023, 2024))
+        );
+        assert_eq!(range("RDATE:20231023T090000Z,20251023T090000Z"), Some((2023, 2025)));
+        assert_eq!(range("RRULE:FREQ=DAILY"), None);
+    }
+
+    #[test]
+    fn test_format_offset() {
+        assert_eq!(format_offset(8 * 3600), "+0800");
+        assert_eq!(format_offset(-(4 * 3600 + 30 * 60)), "-0430");
+        assert_eq!(format_offset(0), "+0000");
+        assert_eq!(format_offset(5 * 3600 + 33 * 60 + 28), "+053328");
+    }
+}
@@ -1,11 +1,33 @@
 mod constant;
+pub mod error;
+pub mod ical;
 pub mod point_time;
 pub mod rrule;
 pub mod rrule_set;
+pub mod timezone;
+pub use error::RRuleError;
+use point_time::{DstPolicy, PointTime};
 use rrule::RRule;
-use rrule_set::RRuleSet;
+use rrule_set::{ExpandOptions, RRuleSet};
+use std::str::FromStr;
 use wasm_bindgen::prelude::*;
 
+#[wasm_bindgen(inline_js = "export function create_error(code, message) {
+  const error = new Error(message);
+  error.code = code;
+  return error;
+}")]
+extern "C" {
+    fn create_error(code: &str, message: &str) -> JsValue;
+}
+
+/// 转换为js的Error，带有message和code
+impl From<RRuleError> for JsValue {
+    fn from(err: RRuleError) -> Self {
+        create_error(err.code(), &err.to_string())
+    }
+}
+
 #[wasm_bindgen]
 pub struct JsRRule {
     rrule: RRule,
@@ -14,10 +36,10 @@ pub struct JsRRule {
 #[wasm_bindgen]
 impl JsRRule {
     #[wasm_bindgen(constructor)]
-    pub fn new(s: &str) -> JsRRule {
-        JsRRule {
-            rrule: RRule::from_str(s),
-        }
+    pub fn new(s: &str) -> Result<JsRRule, JsValue> {
+        Ok(JsRRule {
+            rrule: RRule::from_str(s)?,
+        })
     }
 
     pub fn set_count(&mut self, count: u32) {
@@ -28,47 +50,186 @@ impl JsRRule {
 #[wasm_bindgen]
 pub struct JsRRuleSet {
     rrule_set: RRuleSet,
+    /// 宽松模式解析时的警告
+    warnings: Vec<String>,
+    /// 上一次all的结果是否被截断
+    truncated: bool,
 }
 #[wasm_bindgen]
 impl JsRRuleSet {
     #[wasm_bindgen(constructor)]
-    pub fn new(s: &str) -> JsRRuleSet {
-        JsRRuleSet {
-            rrule_set: RRuleSet::from_str(s).unwrap(),
-        }
+    pub fn new(s: &str) -> Result<JsRRuleSet, JsValue> {
+        Ok(JsRRuleSet {
+            rrule_set: RRuleSet::from_str(s)?,
+            warnings: vec![],
+            truncated: false,
+        })
     }
-    pub fn add_rrule(&mut self, rrule: &str) {
-        self.rrule_set.add_rrule(rrule)
+    #[wasm_bindgen(js_name = "newStrict")]
+    pub fn new_strict(s: &str) -> Result<JsRRuleSet, JsValue> {
+        Ok(JsRRuleSet {
+            rrule_set: RRuleSet::from_str_strict(s)?,
+            warnings: vec![],
+            truncated: false,
+        })
     }
 
-    pub fn tz(&mut self, tz: &str) {
-        self.rrule_set.tz(tz);
+    #[wasm_bindgen(js_name = "newLenient")]
+    pub fn new_lenient(s: &str) -> Result<JsRRuleSet, JsValue> {
+        let (rrule_set, warnings) = RRuleSet::from_str_lenient(s)?;
+        Ok(JsRRuleSet {
+            rrule_set,
+            warnings,
+            truncated: false,
+        })
     }
 
-    pub fn set_dt_start(&mut self, str: &str) {
-        self.rrule_set.set_dt_start(str)
+    /// warnings of the lenient mode, joined by `\n`
+    pub fn warnings(&self) -> String {
+        self.warnings.join("\n")
+    }
+
+    /// whether the result of the last `all` is truncated by the limit or the options
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+    pub fn add_rrule(&mut self, rrule: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.add_rrule(rrule)?)
+    }
+
+    pub fn add_exrule(&mut self, exrule: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.add_exrule(exrule)?)
+    }
+
+    pub fn add_rdate(&mut self, str: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.add_rdate(str)?)
+    }
+
+    pub fn add_exdate(&mut self, str: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.add_exdate(str)?)
+    }
+
+    pub fn remove_exdate(&mut self, str: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.remove_exdate(str)?)
+    }
+
+    pub fn tz(&mut self, tz: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.tz(tz)?)
+    }
+
+    /// use the timezone defined by a VTIMEZONE, for TZIDs like `W. Europe Standard Time`
+    pub fn set_vtimezone(&mut self, vtimezone: &str) -> Result<(), JsValue> {
+        let tz = ical::parse_vtimezone(vtimezone)?;
+        self.rrule_set.set_timezone(tz.into());
+        Ok(())
+    }
+
+    pub fn set_dt_start(&mut self, str: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.set_dt_start(str)?)
+    }
+
+    /// `nonexistent` is `shift_forward` or `skip`, `ambiguous` is `earliest`, `latest` or `skip`
+    pub fn set_dst_policy(&mut self, nonexistent: &str, ambiguous: &str) -> Result<(), JsValue> {
+        self.rrule_set.set_dst_policy(DstPolicy {
+            nonexistent: nonexistent.parse()?,
+            ambiguous: ambiguous.parse()?,
+        });
+        Ok(())
+    }
+
+    /// limits of the expansion, the default ones are used for `undefined`
+    pub fn set_options(
+        &mut self,
+        max_results: Option<usize>,
+        max_iterations: Option<usize>,
+        horizon: Option<String>,
+    ) -> Result<(), JsValue> {
+        let default = ExpandOptions::default();
+        self.rrule_set.set_options(ExpandOptions {
+            max_results: max_results.unwrap_or(default.max_results),
+            max_iterations: max_iterations.unwrap_or(default.max_iterations),
+            horizon: horizon.map(|n| n.parse()).transpose()?,
+        });
+        Ok(())
     }
 
     pub fn set_count(&mut self, count: u32) {
         self.rrule_set.set_count(count);
     }
 
-    pub fn set_until(&mut self, str: &str) {
-        self.rrule_set.set_until(str);
+    pub fn set_until(&mut self, str: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.set_until(str)?)
+    }
+
+    pub fn set_rrule_count(&mut self, index: usize, count: u32) {
+        self.rrule_set.set_rrule_count(index, count);
+    }
+
+    pub fn set_rrule_until(&mut self, index: usize, str: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.set_rrule_until(index, str)?)
+    }
+
+    pub fn set_between(&mut self, start: &str, end: &str) -> Result<(), JsValue> {
+        Ok(self.rrule_set.set_between(start, end)?)
+    }
+
+    pub fn after(&self, dt: &str, inclusive: bool) -> Result<Option<f64>, JsValue> {
+        Ok(self
+            .rrule_set
+            .after(dt, inclusive)?
+            .map(|d| d.timestamp_millis() as f64))
     }
 
-    pub fn between(&mut self, start: &str, end: &str) {
-        self.rrule_set.between(start, end);
+    pub fn before(&self, dt: &str, inclusive: bool) -> Result<Option<f64>, JsValue> {
+        Ok(self
+            .rrule_set
+            .before(dt, inclusive)?
+            .map(|d| d.timestamp_millis() as f64))
     }
 
-    pub fn all(&self) -> String {
-        self.rrule_set
-            .all()
+    pub fn between(&self, start: &str, end: &str, inclusive: bool) -> Result<String, JsValue> {
+        Ok(self
+            .rrule_set
+            .between(start, end, inclusive)?
             .iter()
             .map(|d| d.timestamp_millis().to_string())
             .collect::<Vec<_>>()
+            .join(","))
+    }
+
+    /// timestamps in milliseconds joined by `,`, or dates like `20231115` for an all-day set
+    pub fn all(&mut self, limit: Option<usize>) -> String {
+        let list = match limit {
+            Some(limit) => self.rrule_set.all_with_limit(limit),
+            None => self.rrule_set.all(),
+        };
+        self.truncated = list.truncated;
+        let all_day = self.rrule_set.is_all_day();
+        list.iter()
+            .map(|d| match all_day {
+                true => PointTime::from_naive_date(&d.date_naive()).to_string(),
+                false => d.timestamp_millis().to_string(),
+            })
+            .collect::<Vec<_>>()
             .join(",")
     }
+
+    /// export a VCALENDAR with one VEVENT, `dt_end` uses the timezone of the set
+    #[wasm_bindgen(js_name = "toICal")]
+    pub fn to_ical(
+        &self,
+        uid: &str,
+        summary: Option<String>,
+        dt_end: Option<String>,
+    ) -> Result<String, JsValue> {
+        let props = ical::EventProps {
+            uid: uid.to_string(),
+            summary,
+            dt_end: dt_end.map(|n| n.parse()).transpose()?,
+            dt_stamp: None,
+        };
+        Ok(ical::to_vcalendar(&self.rrule_set, &props))
+    }
     #[wasm_bindgen(js_name="valueOf")]
     pub fn value_of(&self)-> String {
         self.rrule_set.to_string()
@@ -76,7 +237,21 @@ impl JsRRuleSet {
 }
 
 #[wasm_bindgen(js_name="getJsRRuleSet")]
-pub fn get_js_rrule_set(json_str: &str) -> JsRRuleSet {
-    let rrule_set = RRuleSet::from_json(json_str);
-    JsRRuleSet { rrule_set }
+pub fn get_js_rrule_set(json_str: &str) -> Result<JsRRuleSet, JsValue> {
+    let rrule_set = RRuleSet::from_json(json_str)?;
+    Ok(JsRRuleSet {
+        rrule_set,
+        warnings: vec![],
+        truncated: false,
+    })
+}
+
+#[wasm_bindgen(js_name="getJsRRuleSetLenient")]
+pub fn get_js_rrule_set_lenient(json_str: &str) -> Result<JsRRuleSet, JsValue> {
+    let (rrule_set, warnings) = RRuleSet::from_json_lenient(json_str)?;
+    Ok(JsRRuleSet {
+        rrule_set,
+        warnings,
+        truncated: false,
+    })
 }
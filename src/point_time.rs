@@ -1,9 +1,28 @@
-use chrono::{DateTime, NaiveDate, TimeZone};
-use chrono_tz::Tz;
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Timelike,
+};
 use std::cmp::Ordering;
 use std::str::FromStr;
 
-#[derive(PartialEq, Debug, Clone, Eq)]
+use crate::error::RRuleError;
+
+/// The form of a date-time in RFC 5545
+#[derive(PartialEq, Debug, Clone, Copy, Default, Eq)]
+pub enum TimeForm {
+    /// A local time without timezone, like `20231023T090000`
+    #[default]
+    Floating,
+    /// A UTC time, like `20231023T090000Z`
+    Utc,
+    /// A local time in the timezone given by TZID, like `DTSTART;TZID=Asia/Shanghai:20231023T090000`
+    Zoned,
+    /// A date without time, like `DTSTART;VALUE=DATE:20231115`, used by all-day events
+    Date,
+}
+
+/// 不带偏移的时间，form记录原始的写法
+/// 比较时不考虑form，同一个RRuleSet中的时间都已转换到它的时区下
+#[derive(Debug, Clone, Eq)]
 pub struct PointTime {
     pub year: i32,
     pub month: u32,
@@ -11,45 +30,53 @@ pub struct PointTime {
     pub hour: u32,
     pub min: u32,
     pub sec: u32,
+    pub form: TimeForm,
+}
+
+impl PartialEq for PointTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
 }
 
 impl FromStr for PointTime {
-    type Err = String;
-    /// Parses a date string with format `YYYYMMDDTHHMMSSZ`
+    type Err = RRuleError;
+    /// Parses a date string with format `YYYYMMDDTHHMMSSZ`, the `Z` is optional,
+    /// the time with `Z` is [`TimeForm::Utc`] and the one without `Z` is [`TimeForm::Floating`].
+    /// A date with format `YYYYMMDD` is [`TimeForm::Date`], at 00:00:00
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let year: i32 = s[0..4].parse().map_err(|_err| "invalid string")?;
-        let month: u32 = s[4..6]
-            .to_string()
-            .parse()
-            .map_err(|_err| "invalid month")?;
-        let day: u32 = s[6..8].to_string().parse().map_err(|_err| "invalid day")?;
-
-        let t = s[8..9].eq("T");
-        if !t {
-            return Err("invalid string format".to_string());
-        }
-
-        let hour: u32 = s[9..11]
-            .to_string()
-            .parse()
-            .map_err(|_err| "invalid hour")?;
-        let min: u32 = s[11..13]
-            .to_string()
-            .parse()
-            .map_err(|_err| "invalid minute")?;
-        let sec: u32 = s[13..15]
-            .to_string()
-            .parse()
-            .map_err(|_err| "invalid second")?;
-
-        Ok(PointTime {
-            year,
-            month,
-            day,
-            hour,
-            min,
-            sec,
-        })
+        let invalid = || RRuleError::InvalidDate(s.to_string());
+        if s.len() == 8 && s.chars().all(|c| c.is_ascii_digit()) {
+            return Self::from_str(&format!("{}T000000", s))
+                .map(|n| n.with_form(TimeForm::Date))
+                .map_err(|_| invalid());
+        }
+        let (time, form) = match s.strip_suffix('Z') {
+            Some(time) => (time, TimeForm::Utc),
+            None => (s, TimeForm::Floating),
+        };
+        let is_valid_format = time.len() == 15
+            && time
+                .chars()
+                .enumerate()
+                .all(|(i, c)| if i == 8 { c == 'T' } else { c.is_ascii_digit() });
+        if !is_valid_format {
+            return Err(invalid());
+        }
+        let number = |range: std::ops::Range<usize>| time[range].parse::<u32>().unwrap_or_default();
+
+        let point_time = PointTime {
+            year: number(0..4) as i32,
+            month: number(4..6),
+            day: number(6..8),
+            hour: number(9..11),
+            min: number(11..13),
+            sec: number(13..15),
+            form,
+        };
+        // 校验日期和时间是否存在，如 20230230
+        point_time.to_naive_date_time().ok_or_else(invalid)?;
+        Ok(point_time)
     }
 }
 
@@ -108,15 +135,105 @@ impl Ord for PointTime {
     }
 }
 
+/// How to handle a local time skipped when the daylight saving time starts, like 02:30 in America/New_York on 2023-03-12
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum NonexistentTime {
+    /// Use the offset before the transition, so the time is moved forward by the length of the gap, like 02:30 to 03:30, as RFC 5545 suggests
+    #[default]
+    ShiftForward,
+    /// Skip the occurrence
+    Skip,
+}
+
+/// How to handle a local time repeated when the daylight saving time ends, like 01:30 in America/New_York on 2023-11-05
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum AmbiguousTime {
+    /// Use the first one, in daylight saving time, as RFC 5545 suggests
+    #[default]
+    Earliest,
+    /// Use the second one, in standard time
+    Latest,
+    /// Skip the occurrence
+    Skip,
+}
+
+/// The policy to convert a local time which does not exist or is ambiguous in a timezone
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct DstPolicy {
+    pub nonexistent: NonexistentTime,
+    pub ambiguous: AmbiguousTime,
+}
+
+impl FromStr for NonexistentTime {
+    type Err = RRuleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "shift_forward" => Ok(Self::ShiftForward),
+            "skip" => Ok(Self::Skip),
+            _ => Err(RRuleError::invalid_value("NONEXISTENT", s)),
+        }
+    }
+}
+
+impl FromStr for AmbiguousTime {
+    type Err = RRuleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "earliest" => Ok(Self::Earliest),
+            "latest" => Ok(Self::Latest),
+            "skip" => Ok(Self::Skip),
+            _ => Err(RRuleError::invalid_value("AMBIGUOUS", s)),
+        }
+    }
+}
+
 impl PointTime {
-    pub fn with_timezone(&self, tz: &Tz) -> DateTime<Tz> {
-        let rs = tz
-            .with_ymd_and_hms(
-                self.year, self.month, self.day, self.hour, self.min, self.sec,
-            )
-            .single()
-            .unwrap();
-        rs
+    /// 转换为tz下的时间，不存在或重复的时间使用默认的DstPolicy，即RFC 5545的做法
+    pub fn with_timezone<T: TimeZone>(&self, tz: &T) -> DateTime<T> {
+        // 默认的策略不会跳过，只有日期本身无效时才会失败
+        self.with_timezone_policy(tz, &DstPolicy::default())
+            .unwrap_or_else(|| panic!("invalid date: {}", self))
+    }
+
+    /// 转换为tz下的时间，不存在或重复的时间按policy处理，跳过或者日期无效时返回None
+    pub fn with_timezone_policy<T: TimeZone>(
+        &self,
+        tz: &T,
+        policy: &DstPolicy,
+    ) -> Option<DateTime<T>> {
+        let local = self.to_naive_date_time()?;
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(time) => Some(time),
+            LocalResult::Ambiguous(earliest, latest) => match policy.ambiguous {
+                AmbiguousTime::Earliest => Some(earliest),
+                AmbiguousTime::Latest => Some(latest),
+                AmbiguousTime::Skip => None,
+            },
+            LocalResult::None => match policy.nonexistent {
+                NonexistentTime::ShiftForward => {
+                    // 切换前的偏移，两次切换的间隔总是远大于一天
+                    let before = tz
+                        .offset_from_utc_datetime(&(local - Duration::days(1)))
+                        .fix()
+                        .local_minus_utc();
+                    Some(tz.from_utc_datetime(&(local - Duration::seconds(before as i64))))
+                }
+                NonexistentTime::Skip => None,
+            },
+        }
+    }
+
+    /// 把from时区下的时间转换为to时区下的时间，结果为Floating
+    pub fn convert_timezone<F, T>(&self, from: &F, to: &T) -> PointTime
+    where
+        F: TimeZone + PartialEq<T>,
+        T: TimeZone,
+    {
+        if from == to {
+            return self.with_form(TimeForm::Floating);
+        }
+        let time = self.with_timezone(from).with_timezone(to);
+        PointTime::from_naive_date_time(&time.naive_local())
     }
 
     pub fn add_month(&mut self, mon: u32) -> Self {
@@ -136,6 +253,46 @@ impl PointTime {
         next_point_time
     }
 
+    /// 转换为不带时区的时间，无效的时间返回None
+    pub fn to_naive_date_time(&self) -> Option<NaiveDateTime> {
+        NaiveDate::from_ymd_opt(self.year, self.month, self.day)?
+            .and_hms_opt(self.hour, self.min, self.sec)
+    }
+
+    /// 由不带时区的时间生成时间节点
+    pub fn from_naive_date_time(time: &NaiveDateTime) -> PointTime {
+        PointTime {
+            year: time.year(),
+            month: time.month(),
+            day: time.day(),
+            hour: time.hour(),
+            min: time.minute(),
+            sec: time.second(),
+            form: TimeForm::Floating,
+        }
+    }
+
+    /// 由日期生成Date写法的时间节点，时间为0点
+    pub fn from_naive_date(date: &NaiveDate) -> PointTime {
+        PointTime {
+            year: date.year(),
+            month: date.month(),
+            day: date.day(),
+            hour: 0,
+            min: 0,
+            sec: 0,
+            form: TimeForm::Date,
+        }
+    }
+
+    /// 返回相同的时间，form改为指定的写法
+    pub fn with_form(&self, form: TimeForm) -> PointTime {
+        PointTime {
+            form,
+            ..self.clone()
+        }
+    }
+
     /// 判断时间节点是否有效
     pub fn is_valid(&self) -> bool {
         let date_time = NaiveDate::from_ymd_opt(self.year, self.month, self.day);
@@ -162,17 +319,61 @@ impl PointTime {
 use std::fmt;
 impl fmt::Display for PointTime {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.form == TimeForm::Date {
+            return write!(f, "{}{:02}{:02}", self.year, self.month, self.day);
+        }
         write!(
             f,
-            "{}{:02}{:02}T{:02}{:02}{:02}Z",
-            self.year, self.month, self.day, self.hour, self.min, self.sec
+            "{}{:02}{:02}T{:02}{:02}{:02}{}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.min,
+            self.sec,
+            if self.form == TimeForm::Utc { "Z" } else { "" }
         )
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::PointTime;
+    use super::{AmbiguousTime, DstPolicy, NonexistentTime, PointTime, TimeForm};
+    use chrono_tz::Tz;
+
+    #[test]
+    fn with_timezone_policy() {
+        let tz = Tz::America__New_York;
+        let to_utc = |s: &str, policy: DstPolicy| {
+            s.parse::<PointTime>()
+                .unwrap()
+                .with_timezone_policy(&tz, &policy)
+                .map(|n| n.naive_utc().to_string())
+        };
+        let default = DstPolicy::default();
+        let skip = DstPolicy {
+            nonexistent: NonexistentTime::Skip,
+            ambiguous: AmbiguousTime::Skip,
+        };
+        let latest = DstPolicy {
+            ambiguous: AmbiguousTime::Latest,
+            ..Default::default()
+        };
+        // 02:30不存在，按切换前的-0500计算，即03:30 EDT
+        assert_eq!(to_utc("20230312T023000", default), Some("2023-03-12 07:30:00".to_string()));
+        assert_eq!(
+            "20230312T023000".parse::<PointTime>().unwrap().with_timezone(&tz).to_string(),
+            "2023-03-12 03:30:00 EDT"
+        );
+        assert_eq!(to_utc("20230312T023000", skip), None);
+        // 01:30出现两次
+        assert_eq!(to_utc("20231105T013000", default), Some("2023-11-05 05:30:00".to_string()));
+        assert_eq!(to_utc("20231105T013000", latest), Some("2023-11-05 06:30:00".to_string()));
+        assert_eq!(to_utc("20231105T013000", skip), None);
+        assert_eq!(to_utc("20231106T013000", skip), Some("2023-11-06 06:30:00".to_string()));
+        assert_eq!("skip".parse::<NonexistentTime>(), Ok(NonexistentTime::Skip));
+        assert!("later".parse::<AmbiguousTime>().is_err());
+    }
 
     #[test]
     fn parse_timestr() {
@@ -182,6 +383,16 @@ mod test {
         assert_eq!((rs.hour, rs.min, rs.sec), (19, 10, 20))
     }
 
+    #[test]
+    fn parse_invalid_timestr() {
+        for s in ["2023", "20231115191020", "20231115T1910", "2023111ST191020", "20230230T000000", "20231115T250000"] {
+            assert_eq!(
+                s.parse::<PointTime>(),
+                Err(crate::error::RRuleError::InvalidDate(s.to_string()))
+            );
+        }
+    }
+
     #[test]
     fn test_add_month() {
         assert_eq!(
@@ -245,7 +456,42 @@ mod test {
     fn test_to_str() {
         assert_eq!(
             "20231029T191020".parse::<PointTime>().unwrap().to_string(),
+            "20231029T191020"
+        );
+        assert_eq!(
+            "20231029T191020Z".parse::<PointTime>().unwrap().to_string(),
             "20231029T191020Z"
         );
     }
+
+    #[test]
+    fn test_form() {
+        let utc = "20231029T191020Z".parse::<PointTime>().unwrap();
+        let floating = "20231029T191020".parse::<PointTime>().unwrap();
+        assert_eq!(utc.form, TimeForm::Utc);
+        assert_eq!(floating.form, TimeForm::Floating);
+        // 比较时不考虑写法
+        assert_eq!(utc, floating);
+        assert_eq!(floating.with_form(TimeForm::Zoned).to_string(), "20231029T191020");
+        assert_eq!(floating.with_form(TimeForm::Utc).to_string(), "20231029T191020Z");
+    }
+
+    #[test]
+    fn test_date() {
+        let date = "20231115".parse::<PointTime>().unwrap();
+        assert_eq!(date.form, TimeForm::Date);
+        assert_eq!((date.year, date.month, date.day, date.hour), (2023, 11, 15, 0));
+        assert_eq!(date.to_string(), "20231115");
+        assert_eq!(date, "20231115T000000".parse().unwrap());
+        assert_eq!(
+            PointTime::from_naive_date(&chrono::NaiveDate::from_ymd_opt(2023, 11, 15).unwrap()).to_string(),
+            "20231115"
+        );
+        for s in ["20230230", "20231115Z", "2023111A"] {
+            assert_eq!(
+                s.parse::<PointTime>(),
+                Err(crate::error::RRuleError::InvalidDate(s.to_string()))
+            );
+        }
+    }
 }
@@ -1,6 +1,9 @@
+use crate::error::RRuleError;
 use crate::point_time::PointTime;
+use crate::timezone::{get_timezone, Timezone};
 use chrono::{DateTime, Datelike, Duration, Weekday};
 use chrono_tz::Tz;
+use std::fmt;
 use std::str::FromStr;
 
 use self::weekday::{parse_weekdays, str_to_weekday, NWeekday};
@@ -14,77 +17,115 @@ pub enum RRuleProperty {
     Until(PointTime),
     Count(u32),
     Interval(u32),
-    BySecond,
-    ByMinute,
-    ByHour,
+    BySecond(Vec<u8>),
+    ByMinute(Vec<u8>),
+    ByHour(Vec<u8>),
     ByDay(Vec<NWeekday>),
     ByMonthDay(Vec<i16>),
     ByYearDay(Vec<i16>),
     ByWeekNo(Vec<i8>),
     ByMonth(Vec<u8>),
-    BySetPos,
+    BySetPos(Vec<i16>),
     Wkst(Weekday),
+    /// Unknown and X- properties, like `X-NAME=value`
+    Other(String, String),
 }
 
 impl FromStr for RRuleProperty {
-    type Err = String;
+    type Err = RRuleError;
     /// parse str with FREQ=WEEKLY
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let key_value: Vec<&str> = s.split('=').collect();
-        if key_value.len() != 2 {
-            return Err("invalid rrule property".to_string());
-        }
-        let key = key_value[0];
-        let value = key_value[1];
+        // 只按第一个=拆分，X-属性的值中可以有=
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| RRuleError::InvalidProperty(s.to_string()))?;
         let prop = match &key.to_uppercase()[..] {
             "FREQ" => Self::Freq(value.parse()?),
-            "UNTIL" => Self::Until(value.parse()?),
-            "COUNT" => Self::Count(value.parse().unwrap_or(0)),
-            "INTERVAL" => Self::Interval(value.parse().unwrap_or(1)),
-            "BYSECOND" => Self::BySecond,
-            "BYMINUTE" => Self::ByMinute,
-            "BYHOUR" => Self::ByHour,
-            "BYWEEKDAY" | "BYDAY" => Self::ByDay(parse_weekdays(value).unwrap()),
-            "BYMONTHDAY" => Self::ByMonthDay(
+            "UNTIL" => Self::Until(
                 value
-                    .split(",")
-                    .map(|s| s.parse())
-                    .filter(|s| s.is_ok())
-                    .map(|s| s.unwrap())
-                    .collect::<Vec<_>>(),
+                    .parse()
+                    .map_err(|_| RRuleError::invalid_value("UNTIL", value))?,
             ),
-            "BYYEARDAY" => Self::ByYearDay(
+            "COUNT" => Self::Count(
                 value
-                    .split(",")
-                    .map(|s| s.parse::<i16>())
-                    .filter(|s| s.is_ok())
-                    .map(|s| s.unwrap())
-                    .collect::<Vec<_>>(),
+                    .parse()
+                    .map_err(|_| RRuleError::invalid_value("COUNT", value))?,
             ),
-            "BYWEEKNO" => Self::ByWeekNo(
+            "INTERVAL" => Self::Interval(
                 value
-                    .split(",")
-                    .map(|s| s.parse::<i8>())
-                    .filter(|s| s.is_ok())
-                    .map(|s| s.unwrap())
-                    .collect::<Vec<_>>(),
+                    .parse()
+                    .map_err(|_| RRuleError::invalid_value("INTERVAL", value))?,
             ),
-            "BYMONTH" => Self::ByMonth(
-                value
-                    .split(",")
-                    .map(|s| s.parse::<u8>())
-                    .filter(|s| s.is_ok())
-                    .map(|s| s.unwrap())
-                    .collect::<Vec<_>>(),
+            "BYSECOND" => Self::BySecond(parse_time_list(value)),
+            "BYMINUTE" => Self::ByMinute(parse_time_list(value)),
+            "BYHOUR" => Self::ByHour(parse_time_list(value)),
+            "BYWEEKDAY" | "BYDAY" => Self::ByDay(parse_weekdays(value)?),
+            "BYMONTHDAY" => Self::ByMonthDay(parse_number_list("BYMONTHDAY", value, signed(31))?),
+            "BYYEARDAY" => Self::ByYearDay(parse_number_list("BYYEARDAY", value, signed(366))?),
+            "BYWEEKNO" => Self::ByWeekNo(parse_number_list("BYWEEKNO", value, signed(53))?),
+            "BYMONTH" => {
+                Self::ByMonth(parse_number_list("BYMONTH", value, |n| (1..=12).contains(&n))?)
+            }
+            "BYSETPOS" => Self::BySetPos(parse_number_list("BYSETPOS", value, signed(366))?),
+            "WKST" => Self::Wkst(
+                str_to_weekday(value).map_err(|_| RRuleError::invalid_value("WKST", value))?,
             ),
-            "BYSETPOS" => Self::BySetPos,
-            "WKST" => Self::Wkst(str_to_weekday(value).unwrap()),
-            _ => return Err(s.into()),
+            // RFC 7529的扩展属性
+            "RSCALE" | "SKIP" => return Err(RRuleError::Unsupported(key.to_string())),
+            _ if key.is_empty() => return Err(RRuleError::InvalidProperty(s.to_string())),
+            _ => Self::Other(key.to_string(), value.to_string()),
+        };
+        Ok(prop)
+    }
+}
+impl RRuleProperty {
+    /// 严格模式解析，按RFC 5545校验值的范围，列表中任意一个值不合法都会返回错误
+    pub fn from_str_strict(s: &str) -> Result<Self, RRuleError> {
+        let prop: RRuleProperty = s.parse()?;
+        let (key, value) = s.split_once('=').unwrap_or_default();
+        let key = key.to_uppercase();
+        let check = |is_valid: &dyn Fn(i64) -> bool| {
+            match value
+                .split(',')
+                .find(|n| !n.parse::<i64>().is_ok_and(is_valid))
+            {
+                Some(n) => Err(RRuleError::invalid_value(&key, n)),
+                None => Ok(()),
+            }
         };
+        match &prop {
+            Self::Count(_) | Self::Interval(_) => check(&|n| n >= 1)?,
+            Self::BySecond(_) => check(&|n| (0..=60).contains(&n))?,
+            Self::ByMinute(_) => check(&|n| (0..=59).contains(&n))?,
+            Self::ByHour(_) => check(&|n| (0..=23).contains(&n))?,
+            Self::ByMonthDay(_) => check(&signed(31))?,
+            Self::ByYearDay(_) | Self::BySetPos(_) => check(&signed(366))?,
+            Self::ByWeekNo(_) => check(&signed(53))?,
+            Self::ByMonth(_) => check(&|n| (1..=12).contains(&n))?,
+            Self::ByDay(days) => {
+                // 带序号时范围为1到53，不能为0
+                let invalid = value
+                    .split(',')
+                    .zip(days)
+                    .find(|(n, day)| n.len() > 2 && !(1..=53).contains(&day.get_nth().abs()));
+                if let Some((n, _)) = invalid {
+                    return Err(RRuleError::invalid_value(&key, n));
+                }
+                if key == "BYWEEKDAY" {
+                    return Err(RRuleError::InvalidProperty(key));
+                }
+            }
+            // RFC 5545只允许X-开头的扩展属性
+            Self::Other(_, _) if !key.starts_with("X-") => {
+                return Err(RRuleError::InvalidProperty(key));
+            }
+            _ => {}
+        }
         Ok(prop)
     }
 }
-#[derive(PartialEq, Debug)]
+
+#[derive(PartialEq, Debug, Clone)]
 pub struct RRule {
     pub freq: Frequency,
     pub until: Option<PointTime>,
@@ -96,9 +137,25 @@ pub struct RRule {
     pub by_year_day: Vec<i16>,
     pub by_week_no: Vec<i8>,
     pub by_month: Vec<u8>,
-    // ByYearDay,
-    // BySetPos,
+    pub by_hour: Vec<u8>,
+    pub by_minute: Vec<u8>,
+    pub by_second: Vec<u8>,
+    pub by_set_pos: Vec<i16>,
+    /// 未知属性和X-属性，按原顺序保存，to_string时原样输出
+    pub other_props: Vec<(String, String)>,
+}
+impl FromStr for RRule {
+    type Err = RRuleError;
+    // 解析字符串，RRULE:FREQ=DAILY;COUNT=3。单行，不处理dt_start
+    fn from_str(rrule_str: &str) -> Result<Self, Self::Err> {
+        let mut rrule = RRule::default();
+        for part in Self::get_parts(rrule_str) {
+            rrule.set_property(part.parse()?);
+        }
+        Ok(rrule)
+    }
 }
+
 impl RRule {
     pub fn default() -> RRule {
         RRule {
@@ -112,79 +169,160 @@ impl RRule {
             by_year_day: vec![],
             by_week_no: vec![],
             by_month: vec![],
+            by_hour: vec![],
+            by_minute: vec![],
+            by_second: vec![],
+            by_set_pos: vec![],
+            other_props: vec![],
         }
     }
-    // 解析字符串，RRULE:FREQ=DAILY;COUNT=3。单行，不处理dt_start
-    pub fn from_str(rrule_str: &str) -> RRule {
-        let mut freq: Frequency = Frequency::Weekly;
-        let mut count: u32 = 0;
-        let mut until: Option<PointTime> = None;
-        let mut by_day = vec![];
-        let mut interval = 1;
-        let mut week_start = Weekday::Sun;
-        let mut by_month_day: Vec<i16> = vec![];
-        let mut by_month: Vec<u8> = vec![];
-        let mut by_year_day: Vec<i16> = vec![];
-        let mut by_week_no: Vec<i8> = vec![];
-        let lines: Vec<&str> = rrule_str.split(':').collect();
-        let parts: Vec<&str> = if lines.len() == 2 {
-            lines[1].split(";").collect()
-        } else {
-            lines[0].split(";").collect()
-        };
+    /// 严格模式解析，校验规则不符合RFC 5545时返回第一个错误
+    pub fn from_str_strict(rrule_str: &str) -> Result<RRule, RRuleError> {
+        if let Some(err) = Self::validate(rrule_str).into_iter().next() {
+            return Err(err);
+        }
+        Self::from_str(rrule_str)
+    }
 
-        for part in parts {
-            let key_value: Vec<&str> = part.split('=').collect();
-            if key_value.len() == 2 {
-                let key: RRuleProperty = part.parse().unwrap();
-                match key {
-                    RRuleProperty::Freq(f) => {
-                        freq = f;
-                    }
-                    RRuleProperty::Count(c) => {
-                        count = c;
-                    }
-                    RRuleProperty::Until(p) => {
-                        until = Some(p);
-                    }
-                    RRuleProperty::ByDay(days) => {
-                        by_day = days;
-                    }
-                    RRuleProperty::Interval(number) => {
-                        interval = number;
-                    }
-                    RRuleProperty::Wkst(day) => {
-                        week_start = day;
-                    }
-                    RRuleProperty::ByMonthDay(vec) => {
-                        by_month_day = vec;
-                    }
-                    RRuleProperty::ByMonth(vec) => {
-                        by_month = vec;
-                    }
-                    RRuleProperty::ByYearDay(vec) => {
-                        by_year_day = vec;
-                    }
-                    RRuleProperty::ByWeekNo(vec) => {
-                        by_week_no = vec;
-                    }
-                    // 其他RRule的参数，可以在这里处理
-                    _ => {}
-                }
+    /// 按RFC 5545校验规则，返回所有的错误
+    /// - 每个值都在规定的范围内
+    /// - 每个属性只能出现一次，且必须有FREQ
+    /// - COUNT和UNTIL不能同时出现，BYWEEKNO只能用于YEARLY，带序号的BYDAY只能用于MONTHLY和YEARLY等
+    pub fn validate(rrule_str: &str) -> Vec<RRuleError> {
+        let mut errors = vec![];
+        let mut names: Vec<String> = vec![];
+        let mut rrule = RRule::default();
+        for part in Self::get_parts(rrule_str) {
+            let name = part.split('=').next().unwrap_or_default().to_uppercase();
+            if names.contains(&name) {
+                errors.push(RRuleError::InvalidRule(format!("{} occurs more than once", name)));
+            }
+            match RRuleProperty::from_str_strict(part) {
+                Ok(prop) => rrule.set_property(prop),
+                Err(err) => errors.push(err),
             }
+            names.push(name);
         }
-        RRule {
-            freq,
-            count,
-            until,
-            by_day,
-            interval,
-            week_start,
-            by_month_day,
-            by_month,
-            by_year_day,
-            by_week_no,
-            ..RRule::default()
+
+        let freq = rrule.freq;
+        let mut invalid = |message: String| errors.push(RRuleError::InvalidRule(message));
+        if !names.iter().any(|n| n == "FREQ") {
+            invalid("FREQ is required".to_string());
+        }
+        if rrule.count != 0 && rrule.until.is_some() {
+            invalid("COUNT and UNTIL must not occur in the same rule".to_string());
+        }
+        if !rrule.by_week_no.is_empty() && freq != Frequency::Yearly {
+            invalid(format!("BYWEEKNO is not valid with FREQ={}", freq));
+        }
+        if rrule.by_day.iter().any(|n| !n.is_every()) {
+            if freq != Frequency::Monthly && freq != Frequency::Yearly {
+                invalid(format!("numbered BYDAY is not valid with FREQ={}", freq));
+            } else if !rrule.by_week_no.is_empty() {
+                invalid("numbered BYDAY is not valid with BYWEEKNO".to_string());
+            }
+        }
+        if !rrule.by_month_day.is_empty() && freq == Frequency::Weekly {
+            invalid(format!("BYMONTHDAY is not valid with FREQ={}", freq));
+        }
+        if !rrule.by_year_day.is_empty()
+            && matches!(freq, Frequency::Monthly | Frequency::Weekly | Frequency::Daily)
+        {
+            invalid(format!("BYYEARDAY is not valid with FREQ={}", freq));
+        }
+        let has_other_by = names
+            .iter()
+            .any(|n| n.starts_with("BY") && n != "BYSETPOS");
+        if !rrule.by_set_pos.is_empty() && !has_other_by {
+            invalid("BYSETPOS must be used with another BYxxx rule part".to_string());
+        }
+        errors
+    }
+
+    /// 宽松模式解析，修正各种日历客户端导出的不规范写法，不会失败，返回解析结果和警告
+    /// - 属性名不区分大小写，去掉分隔符两边的空白和多余的分号
+    /// - 重复的属性以最后一个为准
+    /// - 无法解析的属性和列表中无法解析的值被忽略
+    pub fn from_str_lenient(rrule_str: &str) -> (RRule, Vec<String>) {
+        let mut warnings = vec![];
+        let mut names: Vec<String> = vec![];
+        let mut rrule = RRule::default();
+        let normalized = trim_separators(rrule_str.trim());
+        if normalized != rrule_str {
+            warnings.push(format!("whitespace is removed: {}", rrule_str.trim()));
+        }
+        // 末尾的分号很常见，直接去掉
+        let value = Self::strip_name(&normalized).trim_end_matches(';');
+        for part in value.split(';') {
+            if part.is_empty() {
+                warnings.push("empty property is ignored".to_string());
+                continue;
+            }
+            let Some((key, value)) = part.split_once('=') else {
+                warnings.push(format!("{}, ignored", RRuleError::InvalidProperty(part.to_string())));
+                continue;
+            };
+            let name = key.to_uppercase();
+            if name != key {
+                warnings.push(format!("property name {} is converted to uppercase", key));
+            }
+            if names.contains(&name) {
+                warnings.push(format!("duplicated property {}, the last one is used", name));
+            }
+            if matches!(
+                &name[..],
+                "BYSECOND" | "BYMINUTE" | "BYHOUR" | "BYMONTHDAY"
+                    | "BYYEARDAY" | "BYWEEKNO" | "BYMONTH" | "BYSETPOS"
+            ) {
+                value
+                    .split(',')
+                    .filter(|n| n.parse::<i64>().is_err())
+                    .for_each(|n| {
+                        warnings.push(format!("{}, ignored", RRuleError::invalid_value(&name, n)))
+                    });
+            }
+            match format!("{}={}", name, value).parse::<RRuleProperty>() {
+                Ok(prop) => rrule.set_property(prop),
+                Err(err) => warnings.push(format!("{}, ignored", err)),
+            }
+            names.push(name);
+        }
+        (rrule, warnings)
+    }
+
+    /// 去掉RRULE:前缀，按;拆分
+    fn get_parts(rrule_str: &str) -> Vec<&str> {
+        Self::strip_name(rrule_str)
+            .split(';')
+            .filter(|n| !n.is_empty())
+            .collect()
+    }
+
+    /// 去掉RRULE:、EXRULE:等前缀
+    fn strip_name(rrule_str: &str) -> &str {
+        match rrule_str.split_once(':') {
+            Some((_, value)) => value,
+            None => rrule_str,
+        }
+    }
+
+    fn set_property(&mut self, prop: RRuleProperty) {
+        match prop {
+            RRuleProperty::Freq(f) => self.freq = f,
+            RRuleProperty::Count(c) => self.count = c,
+            RRuleProperty::Until(p) => self.until = Some(p),
+            RRuleProperty::ByDay(days) => self.by_day = days,
+            RRuleProperty::Interval(number) => self.interval = number,
+            RRuleProperty::Wkst(day) => self.week_start = day,
+            RRuleProperty::ByMonthDay(vec) => self.by_month_day = vec,
+            RRuleProperty::ByMonth(vec) => self.by_month = vec,
+            RRuleProperty::ByYearDay(vec) => self.by_year_day = vec,
+            RRuleProperty::ByWeekNo(vec) => self.by_week_no = vec,
+            RRuleProperty::ByHour(vec) => self.by_hour = vec,
+            RRuleProperty::ByMinute(vec) => self.by_minute = vec,
+            RRuleProperty::BySecond(vec) => self.by_second = vec,
+            RRuleProperty::BySetPos(vec) => self.by_set_pos = vec,
+            RRuleProperty::Other(key, value) => self.other_props.push((key, value)),
         }
     }
 
@@ -192,34 +330,200 @@ impl RRule {
         self.count = count;
     }
 
-    pub fn set_until(&mut self, str: &str) {
-        let point: Result<PointTime, String> = str.parse();
-        if point.is_ok() {
-            self.until = Some(point.unwrap())
+    pub fn set_until(&mut self, str: &str) -> Result<(), RRuleError> {
+        self.until = Some(str.parse::<PointTime>()?);
+        Ok(())
+    }
+}
+
+impl fmt::Display for RRule {
+    /// 输出RRULE的值部分，如 FREQ=DAILY;COUNT=3，不包含 RRULE: 前缀
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |list: Vec<String>| list.join(",");
+        let mut parts: Vec<String> = vec![format!("FREQ={}", self.freq)];
+        if self.count != 0 {
+            parts.push(format!("COUNT={}", self.count));
+        }
+        if let Some(until) = &self.until {
+            parts.push(format!("UNTIL={}", until));
+        }
+        if self.interval != 1 {
+            parts.push(format!("INTERVAL={}", self.interval));
+        }
+        if !self.by_day.is_empty() {
+            parts.push(format!(
+                "BYDAY={}",
+                join(self.by_day.iter().map(|n| n.to_string()).collect())
+            ));
+        }
+        parts.push(format!("WKST={}", NWeekday::weekday_to_str(&self.week_start)));
+        if !self.by_week_no.is_empty() {
+            parts.push(format!(
+                "BYWEEKNO={}",
+                join(self.by_week_no.iter().map(|n| n.to_string()).collect())
+            ));
+        }
+        if !self.by_month_day.is_empty() {
+            parts.push(format!(
+                "BYMONTHDAY={}",
+                join(self.by_month_day.iter().map(|n| n.to_string()).collect())
+            ));
+        }
+        if !self.by_month.is_empty() {
+            parts.push(format!(
+                "BYMONTH={}",
+                join(self.by_month.iter().map(|n| n.to_string()).collect())
+            ));
+        }
+        if !self.by_year_day.is_empty() {
+            parts.push(format!(
+                "BYYEARDAY={}",
+                join(self.by_year_day.iter().map(|n| n.to_string()).collect())
+            ));
+        }
+        for (key, list) in [
+            ("BYHOUR", &self.by_hour),
+            ("BYMINUTE", &self.by_minute),
+            ("BYSECOND", &self.by_second),
+        ] {
+            if !list.is_empty() {
+                parts.push(format!(
+                    "{}={}",
+                    key,
+                    join(list.iter().map(|n| n.to_string()).collect())
+                ));
+            }
+        }
+        if !self.by_set_pos.is_empty() {
+            parts.push(format!(
+                "BYSETPOS={}",
+                join(self.by_set_pos.iter().map(|n| n.to_string()).collect())
+            ));
+        }
+        for (key, value) in self.other_props.iter() {
+            parts.push(format!("{}={}", key, value));
+        }
+        write!(f, "{}", parts.join(";"))
+    }
+}
+
+/// 按RFC 5545展开折叠的行，以空格或tab开头的行是上一行的延续，去掉换行和这一个空白字符
+pub fn unfold_lines(s: &str) -> String {
+    s.replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "")
+}
+
+/// 去掉分隔符两边的空白，如 `RRULE: FREQ = DAILY ;` 转为 `RRULE:FREQ=DAILY;`
+pub(crate) fn trim_separators(line: &str) -> String {
+    let mut result = String::new();
+    let mut token = String::new();
+    for c in line.chars() {
+        if [':', ';', '=', ','].contains(&c) {
+            result += token.trim();
+            result.push(c);
+            token.clear();
+        } else {
+            token.push(c);
+        }
+    }
+    result += token.trim();
+    result
+}
+
+/// 正负数都可以，不能为0，绝对值不超过max
+fn signed(max: i64) -> impl Fn(i64) -> bool {
+    move |n| n != 0 && n.abs() <= max
+}
+
+/// 解析数字列表，无法解析的值被忽略，超出RFC 5545范围的值返回错误，避免展开时使用不存在的日期或位置
+fn parse_number_list<T: TryFrom<i64>>(
+    name: &str,
+    value: &str,
+    is_valid: impl Fn(i64) -> bool,
+) -> Result<Vec<T>, RRuleError> {
+    let mut list = vec![];
+    for n in value.split(',') {
+        let Ok(number) = n.parse::<i64>() else {
+            continue;
+        };
+        match T::try_from(number) {
+            Ok(item) if is_valid(number) => list.push(item),
+            _ => return Err(RRuleError::invalid_value(name, n)),
         }
     }
+    Ok(list)
+}
+
+/// 解析BYHOUR、BYMINUTE、BYSECOND的值，排序并去重
+fn parse_time_list(value: &str) -> Vec<u8> {
+    let mut list = value
+        .split(',')
+        .filter_map(|s| s.parse::<u8>().ok())
+        .collect::<Vec<_>>();
+    list.sort();
+    list.dedup();
+    list
 }
 
-pub fn get_tz_from_str(tz: &str) -> Tz {
-    let chrono_tz: Tz = tz.parse().unwrap();
-    chrono_tz
+pub fn get_tz_from_str(tz: &str) -> Result<Tz, RRuleError> {
+    tz.parse()
+        .map_err(|_| RRuleError::InvalidTimezone(tz.to_string()))
 }
 
-pub fn parse_dt_strart_str(s: &str) -> Result<PointTime, String> {
-    let key_value: Vec<_> = s.split(":").collect();
-    key_value[1].parse()
+pub fn parse_dt_strart_str(s: &str) -> Result<PointTime, RRuleError> {
+    let (_, value) = s
+        .split_once(':')
+        .ok_or_else(|| RRuleError::InvalidProperty(s.to_string()))?;
+    value.parse()
+}
+/// 获取tz和开始时间，TZID不是IANA时区时在timezones中按名称查找
+/// - VALUE=DATE 时为Date写法的日期，如 DTSTART;VALUE=DATE:20231115
+pub fn parse_dt_strart_str_and_tz(
+    s: &str,
+    timezones: &[Timezone],
+) -> Result<(PointTime, Option<Timezone>), RRuleError> {
+    let (list, tz) = parse_date_list_str_and_tz(s, timezones)?;
+    match list[..] {
+        [ref point_time] => Ok((point_time.clone(), tz)),
+        _ => Err(RRuleError::InvalidProperty(s.to_string())),
+    }
 }
-/// 获取tz和开始时间
-pub fn parse_dt_strart_str_and_tz(s: &str) -> Result<(PointTime, Option<Tz>), String> {
-    let key_value: Vec<_> = s.split([':', ';']).collect();
-    if key_value.len() == 2 {
-        let point_time: PointTime = key_value[1].parse()?;
-        return Ok((point_time, None));
+
+/// 解析逗号分隔的时间列表和tz，如 EXDATE;TZID=Asia/Shanghai:20231101T090000,20231102T090000
+/// - VALUE=DATE 时为Date写法的日期
+/// - VALUE=PERIOD 时只取时间段的开始时间，支持 start/end 和 start/duration
+pub fn parse_date_list_str_and_tz(
+    s: &str,
+    timezones: &[Timezone],
+) -> Result<(Vec<PointTime>, Option<Timezone>), RRuleError> {
+    let (head, value) = s
+        .split_once(':')
+        .ok_or_else(|| RRuleError::InvalidProperty(s.to_string()))?;
+    let mut tz: Option<Timezone> = None;
+    let mut value_type = String::from("DATE-TIME");
+    for param in head.split(';').skip(1) {
+        match param.split_once('=') {
+            Some((key, param_value)) => match &key.to_uppercase()[..] {
+                "TZID" => tz = Some(get_timezone(param_value, timezones)?),
+                "VALUE" => value_type = param_value.to_uppercase(),
+                _ => {}
+            },
+            None => return Err(RRuleError::InvalidProperty(s.to_string())),
+        }
     }
-    let tz_str = key_value[1].split("=").collect::<Vec<_>>();
-    let tz: Tz = tz_str[1].parse()?;
-    let point_time: PointTime = key_value[2].parse()?;
-    return Ok((point_time, Some(tz)));
+    let list = value
+        .split(',')
+        .map(|n| n.trim())
+        .map(|n| match &value_type[..] {
+            "DATE" if n.len() == 8 => n.parse::<PointTime>(),
+            "DATE" => Err(RRuleError::InvalidDate(n.to_string())),
+            "PERIOD" => n.split('/').next().unwrap_or_default().parse::<PointTime>(),
+            "DATE-TIME" => n.parse::<PointTime>(),
+            _ => Err(RRuleError::Unsupported(format!("VALUE={}", value_type))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((list, tz))
 }
 
 /// 找到从给定时间起的下一个指定的星期几的时刻
@@ -244,12 +548,16 @@ mod test {
     #[test]
     fn parse_tz() {
         let s = String::from("America/New_York");
-        assert_eq!(get_tz_from_str(&s), Tz::America__New_York);
+        assert_eq!(get_tz_from_str(&s), Ok(Tz::America__New_York));
+        assert_eq!(
+            get_tz_from_str("Mars/Olympus"),
+            Err(RRuleError::InvalidTimezone("Mars/Olympus".to_string()))
+        );
     }
 
     #[test]
     fn parse_time_with_tz() {
-        let tz: Tz = get_tz_from_str("America/New_York");
+        let tz: Tz = get_tz_from_str("America/New_York").unwrap();
         let time: DateTime<Tz> = "20231115T191020"
             .parse::<PointTime>()
             .unwrap()
@@ -279,4 +587,128 @@ mod test {
         let rrule = RRule::from_str(s);
         assert_eq!(rrule, RRule::from_str("FREQ=DAILY;COUNT=3;BYDAY=TU,WE"))
     }
+
+    #[test]
+    fn test_invalid_rrule() {
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=DAILY;=1"),
+            Err(RRuleError::InvalidProperty("=1".to_string()))
+        );
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=DAILY;COUNT"),
+            Err(RRuleError::InvalidProperty("COUNT".to_string()))
+        );
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=FORTNIGHTLY"),
+            Err(RRuleError::invalid_value("FREQ", "FORTNIGHTLY"))
+        );
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=WEEKLY;BYDAY=MO,XX"),
+            Err(RRuleError::invalid_value("BYDAY", "XX"))
+        );
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=WEEKLY;UNTIL=20231345T000000Z"),
+            Err(RRuleError::invalid_value("UNTIL", "20231345T000000Z"))
+        );
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=DAILY;COUNT=abc"),
+            Err(RRuleError::invalid_value("COUNT", "abc"))
+        );
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=DAILY;INTERVAL=-1"),
+            Err(RRuleError::invalid_value("INTERVAL", "-1"))
+        );
+        assert_eq!(
+            RRule::from_str("RRULE:FREQ=YEARLY;RSCALE=CHINESE"),
+            Err(RRuleError::Unsupported("RSCALE".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_strict_rrule() {
+        let invalid_value = |property: &str, value: &str| vec![RRuleError::invalid_value(property, value)];
+        let invalid_rule = |message: &str| vec![RRuleError::InvalidRule(message.to_string())];
+        let test_vec = vec![
+            ("FREQ=YEARLY;BYMONTH=1,13", invalid_value("BYMONTH", "13")),
+            ("FREQ=MONTHLY;BYMONTHDAY=1,X,3", invalid_value("BYMONTHDAY", "X")),
+            ("FREQ=MONTHLY;BYMONTHDAY=40", invalid_value("BYMONTHDAY", "40")),
+            ("FREQ=MONTHLY;BYMONTHDAY=0", invalid_value("BYMONTHDAY", "0")),
+            ("FREQ=YEARLY;BYYEARDAY=-367", invalid_value("BYYEARDAY", "-367")),
+            ("FREQ=YEARLY;BYWEEKNO=54", invalid_value("BYWEEKNO", "54")),
+            ("FREQ=DAILY;BYHOUR=24", invalid_value("BYHOUR", "24")),
+            ("FREQ=DAILY;BYMINUTE=60", invalid_value("BYMINUTE", "60")),
+            ("FREQ=DAILY;BYSECOND=61", invalid_value("BYSECOND", "61")),
+            ("FREQ=DAILY;COUNT=abc", invalid_value("COUNT", "abc")),
+            ("FREQ=DAILY;INTERVAL=0", invalid_value("INTERVAL", "0")),
+            ("FREQ=MONTHLY;BYDAY=54MO", invalid_value("BYDAY", "54MO")),
+            ("FREQ=MONTHLY;BYDAY=0MO", invalid_value("BYDAY", "0MO")),
+            ("FREQ=DAILY;BYWEEKDAY=MO", vec![RRuleError::InvalidProperty("BYWEEKDAY".to_string())]),
+            ("COUNT=3", invalid_rule("FREQ is required")),
+            (
+                "FREQ=DAILY;COUNT=3;UNTIL=20231023T000000Z",
+                invalid_rule("COUNT and UNTIL must not occur in the same rule"),
+            ),
+            ("FREQ=MONTHLY;BYWEEKNO=1", invalid_rule("BYWEEKNO is not valid with FREQ=MONTHLY")),
+            ("FREQ=WEEKLY;BYDAY=1MO", invalid_rule("numbered BYDAY is not valid with FREQ=WEEKLY")),
+            (
+                "FREQ=YEARLY;BYWEEKNO=1;BYDAY=1MO",
+                invalid_rule("numbered BYDAY is not valid with BYWEEKNO"),
+            ),
+            ("FREQ=WEEKLY;BYMONTHDAY=1", invalid_rule("BYMONTHDAY is not valid with FREQ=WEEKLY")),
+            ("FREQ=DAILY;BYYEARDAY=1", invalid_rule("BYYEARDAY is not valid with FREQ=DAILY")),
+            (
+                "FREQ=DAILY;BYSETPOS=1",
+                invalid_rule("BYSETPOS must be used with another BYxxx rule part"),
+            ),
+            ("FREQ=DAILY;COUNT=3;COUNT=4", invalid_rule("COUNT occurs more than once")),
+            (
+                "FREQ=WEEKLY;BYMONTH=13;COUNT=3;UNTIL=20231023T000000Z",
+                vec![
+                    RRuleError::invalid_value("BYMONTH", "13"),
+                    RRuleError::InvalidRule("COUNT and UNTIL must not occur in the same rule".to_string()),
+                ],
+            ),
+            ("RRULE:FREQ=MONTHLY;COUNT=3;BYDAY=-1FR,MO;BYMONTH=1,12;BYSETPOS=1", vec![]),
+        ];
+        for (s, errors) in test_vec {
+            assert_eq!(RRule::validate(s), errors, "{}", s);
+            assert_eq!(RRule::from_str_strict(s).err(), errors.into_iter().next());
+        }
+        // 默认模式下只校验值的范围，不校验属性的组合
+        assert!(RRule::from_str("FREQ=YEARLY;BYWEEKNO=1;COUNT=3;UNTIL=20231023T000000Z").is_ok());
+        assert_eq!(
+            RRule::from_str("FREQ=YEARLY;BYMONTH=13"),
+            Err(RRuleError::invalid_value("BYMONTH", "13"))
+        );
+    }
+
+    #[test]
+    fn test_lenient_rrule() {
+        let (rrule, warnings) = RRule::from_str_lenient(
+            " rrule: freq = daily ; COUNT=3;byday=MO, TU;COUNT=5;BYMONTHDAY=1,x;;FOO;BYMONTH=13;WKST=XX;INTERVAL=x; ",
+        );
+        assert_eq!(
+            rrule,
+            RRule::from_str("FREQ=DAILY;COUNT=5;BYDAY=MO,TU;BYMONTHDAY=1").unwrap()
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "whitespace is removed: rrule: freq = daily ; COUNT=3;byday=MO, TU;COUNT=5;BYMONTHDAY=1,x;;FOO;BYMONTH=13;WKST=XX;INTERVAL=x;",
+                "property name freq is converted to uppercase",
+                "property name byday is converted to uppercase",
+                "duplicated property COUNT, the last one is used",
+                "invalid value of BYMONTHDAY: x, ignored",
+                "empty property is ignored",
+                "invalid property: FOO, ignored",
+                "invalid value of BYMONTH: 13, ignored",
+                "invalid value of WKST: XX, ignored",
+                "invalid value of INTERVAL: x, ignored",
+            ]
+        );
+
+        let (rrule, warnings) = RRule::from_str_lenient("RRULE:FREQ=WEEKLY;BYDAY=MO;");
+        assert_eq!(rrule, RRule::from_str("RRULE:FREQ=WEEKLY;BYDAY=MO").unwrap());
+        assert!(warnings.is_empty());
+    }
 }
@@ -1,6 +1,9 @@
 use std::{fmt::Display, str::FromStr};
 
-#[derive(PartialEq, Debug)]
+use crate::error::RRuleError;
+
+/// The order follows the length of the period, `Yearly` is the largest one.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
 pub enum Frequency {
     /// The recurrence occurs on a yearly basis.
     Yearly = 0,
@@ -19,7 +22,7 @@ pub enum Frequency {
 }
 
 impl FromStr for Frequency {
-    type Err = String;
+    type Err = RRuleError;
     fn from_str(value: &str) -> Result<Self, Self::Err> {
         let freq = match &value.to_uppercase()[..] {
             "YEARLY" => Self::Yearly,
@@ -29,7 +32,7 @@ impl FromStr for Frequency {
             "HOURLY" => Self::Hourly,
             "MINUTELY" => Self::Minutely,
             "SECONDLY" => Self::Secondly,
-            val => return Err(format!("invalid freq: {}", val.to_string())),
+            _ => return Err(RRuleError::invalid_value("FREQ", value)),
         };
         Ok(freq)
     }
@@ -2,9 +2,9 @@ use chrono::Weekday;
 use core::fmt;
 use std::str::FromStr;
 
-use crate::rrule::weekday;
+use crate::error::RRuleError;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum NWeekday {
     /// When it is every weekday of the month or year.
     Every(Weekday),
@@ -71,19 +71,23 @@ impl NWeekday {
 }
 
 impl FromStr for NWeekday {
-    type Err = String;
+    type Err = RRuleError;
 
     /// Generates an [`NWeekday`] from a string.
     fn from_str(value: &str) -> Result<Self, Self::Err> {
         let length = value.len();
 
-        if length < 2 {
-            return Err(value.into());
+        let invalid = || RRuleError::invalid_value("BYDAY", value);
+        if length < 2 || !value.is_ascii() {
+            return Err(invalid());
         }
 
         // it doesn't have any issue, because we checked the string is ASCII above
-        let wd = str_to_weekday(&value[(length - 2)..]).map_err(|_| value.to_string())?;
-        let nth = value[..(length - 2)].parse::<i16>().unwrap_or_default();
+        let wd = str_to_weekday(&value[(length - 2)..]).map_err(|_| invalid())?;
+        let nth = match &value[..(length - 2)] {
+            "" => 0,
+            nth => nth.parse::<i16>().map_err(|_| invalid())?,
+        };
 
         if nth == 0 {
             Ok(Self::Every(wd))
@@ -125,7 +129,7 @@ pub(crate) fn str_to_weekday(d: &str) -> Result<Weekday, String> {
 /// Example: `SU,MO,TU,WE,TH,FR` or `4MO` or `-1WE`
 /// > For example, within a MONTHLY rule, +1MO (or simply 1MO) represents the first Monday
 /// > within the month, whereas -1MO represents the last Monday of the month.
-pub(crate) fn parse_weekdays(val: &str) -> Result<Vec<NWeekday>, String> {
+pub(crate) fn parse_weekdays(val: &str) -> Result<Vec<NWeekday>, RRuleError> {
     let mut wdays = vec![];
     // Separate all days
     for day in val.split(',') {
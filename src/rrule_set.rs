@@ -1,937 +1,1013 @@
 use crate::constant;
-use crate::point_time::PointTime;
+use crate::error::RRuleError;
+use crate::point_time::{DstPolicy, PointTime, TimeForm};
 use crate::rrule::weekday::NWeekday;
-use crate::rrule::{get_tz_from_str, parse_dt_strart_str_and_tz, RRule};
-use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Weekday};
+use crate::timezone::Timezone;
+use crate::rrule::{
+    get_tz_from_str, parse_date_list_str_and_tz, parse_dt_strart_str_and_tz, trim_separators,
+    unfold_lines, Frequency, RRule,
+};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, Timelike, Weekday};
 use chrono_tz::Tz;
 use serde_json::Value;
+use std::ops::Deref;
+use std::str::FromStr;
+
+mod iter;
+pub use iter::{RRuleIter, RRuleSetIter};
 
 #[derive(Debug)]
 pub struct RRuleSet {
     pub rrule: Vec<RRule>,
-    pub tz: Tz,
+    /// 排除规则，展开的时间会从结果中去掉
+    pub exrule: Vec<RRule>,
+    pub tz: Timezone,
     pub start_point_time: Option<PointTime>,
-    pub max_until_time: PointTime,
+    /// 额外加入的时间，已转换到tz下
+    pub rdate: Vec<PointTime>,
+    /// 排除的时间，已转换到tz下
+    pub exdate: Vec<PointTime>,
+    /// 未知的行和X-行，按原顺序保存，to_string时原样输出
+    pub other_lines: Vec<String>,
     pub between_start: Option<PointTime>,
     pub between_end: Option<PointTime>,
+    /// 夏令时切换时不存在或重复的时间的处理方式，默认为RFC 5545的做法
+    pub dst_policy: DstPolicy,
+    /// 展开的限制
+    pub options: ExpandOptions,
+}
+
+/// The limits of the expansion of a [`RRuleSet`]
+#[derive(PartialEq, Debug, Clone)]
+pub struct ExpandOptions {
+    /// The max number of occurrences returned by `all`, default is 65535
+    pub max_results: usize,
+    /// The max number of periods expanded by the rules, like the days of `FREQ=DAILY`, default is 10000000
+    pub max_iterations: usize,
+    /// The rules are not expanded after this time, which is in the timezone of the set, or in UTC when it ends with `Z`.
+    /// `None` means the end of the year 9999
+    pub horizon: Option<PointTime>,
+}
+
+impl Default for ExpandOptions {
+    fn default() -> Self {
+        ExpandOptions {
+            max_results: constant::MAX_ALL_COUNT,
+            max_iterations: constant::MAX_ITERATIONS,
+            horizon: None,
+        }
+    }
+}
+
+/// The occurrences returned by `all`, derefs to the list
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Occurrences {
+    pub list: Vec<DateTime<Timezone>>,
+    /// Whether some occurrences are dropped because of the limit or the [`ExpandOptions`]
+    pub truncated: bool,
+}
+
+impl Deref for Occurrences {
+    type Target = Vec<DateTime<Timezone>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.list
+    }
+}
+
+impl IntoIterator for Occurrences {
+    type Item = DateTime<Timezone>;
+    type IntoIter = std::vec::IntoIter<DateTime<Timezone>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.into_iter()
+    }
+}
+
+impl<T> PartialEq<Vec<T>> for Occurrences
+where
+    DateTime<Timezone>: PartialEq<T>,
+{
+    fn eq(&self, other: &Vec<T>) -> bool {
+        self.list == *other
+    }
+}
+
+impl FromStr for RRuleSet {
+    type Err = RRuleError;
+    /// 解析整个字符串，每行一个DTSTART、RRULE、EXRULE、RDATE或EXDATE，不带前缀的行按RRULE处理
+    /// - 每种行的数量和顺序不限，但只能有一个DTSTART
+    /// - 先按RFC 5545展开折叠的行
+    /// - 其他的行保存在other_lines中
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, &[], &mut RRule::from_str)
+    }
 }
 
 impl RRuleSet {
-    // 解析整个字符串，单行，不处理dt_start
-    pub fn from_str(s: &str) -> Result<RRuleSet, String> {
-        let lines: Vec<_> = s.split("\n").collect();
-        let rrule: RRule;
-        let mut tz = Tz::UTC;
+
+    /// 同from_str，TZID不是IANA时区时，使用timezones中名称相同的时区，如从VTIMEZONE解析的时区
+    pub fn from_str_with_timezones(s: &str, timezones: &[Timezone]) -> Result<RRuleSet, RRuleError> {
+        Self::parse(s, timezones, &mut RRule::from_str)
+    }
+
+    /// 同from_str，但RRULE和EXRULE按RFC 5545严格校验，见`RRule::validate`
+    pub fn from_str_strict(s: &str) -> Result<RRuleSet, RRuleError> {
+        Self::parse(s, &[], &mut RRule::from_str_strict)
+    }
+
+    /// 宽松模式，修正各种日历客户端导出的不规范写法，返回解析结果和警告，见`RRule::from_str_lenient`
+    /// - 支持 `\r\n` 换行，行名不区分大小写，去掉分隔符两边的空白
+    /// - 只有DTSTART、RDATE、EXDATE无法解析时才会失败
+    pub fn from_str_lenient(s: &str) -> Result<(RRuleSet, Vec<String>), RRuleError> {
+        let mut warnings = vec![];
+        if s.contains('\r') {
+            warnings.push("\\r\\n line endings are converted to \\n".to_string());
+        }
+        let normalized = unfold_lines(s)
+            .lines()
+            .map(|line| {
+                let normalized = trim_separators(line.trim());
+                if normalized != line.trim() {
+                    warnings.push(format!("whitespace is removed: {}", line.trim()));
+                }
+                normalized
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let set = Self::parse(&normalized, &[], &mut |line| {
+            let (rrule, rrule_warnings) = RRule::from_str_lenient(line);
+            warnings.extend(rrule_warnings);
+            Ok(rrule)
+        })?;
+        Ok((set, warnings))
+    }
+
+    fn parse(
+        s: &str,
+        timezones: &[Timezone],
+        parse_rrule: &mut dyn FnMut(&str) -> Result<RRule, RRuleError>,
+    ) -> Result<RRuleSet, RRuleError> {
+        let mut rrule: Vec<RRule> = vec![];
+        let mut exrule: Vec<RRule> = vec![];
+        let mut tz = Timezone::UTC;
         let mut start_point_time: Option<PointTime> = None;
-        if lines.len() == 2 {
-            let (start, tz2) = parse_dt_strart_str_and_tz(lines[0])?;
-            start_point_time = Some(start);
-            if tz2.is_some() {
-                tz = tz2.unwrap();
+        let mut rdate_lines: Vec<&str> = vec![];
+        let mut exdate_lines: Vec<&str> = vec![];
+        let mut other_lines: Vec<String> = vec![];
+
+        let s = unfold_lines(s);
+        for line in s.lines().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            let name = line.split([':', ';']).next().unwrap_or_default();
+            match &name.to_uppercase()[..] {
+                "DTSTART" => {
+                    if start_point_time.is_some() {
+                        return Err(RRuleError::InvalidRule(
+                            "DTSTART occurs more than once".to_string(),
+                        ));
+                    }
+                    let (start, tz2) = parse_dt_strart_str_and_tz(line, timezones)?;
+                    // 有TZID时忽略Z，按TZID下的当地时间处理，日期的写法不变
+                    start_point_time = Some(match tz2 {
+                        Some(tz2) => {
+                            tz = tz2;
+                            Self::form_in(&tz, &start)
+                        }
+                        None => start,
+                    });
+                }
+                "EXRULE" => exrule.push(parse_rrule(line)?),
+                "RDATE" => rdate_lines.push(line),
+                "EXDATE" => exdate_lines.push(line),
+                "RRULE" => rrule.push(parse_rrule(line)?),
+                // 不带前缀的规则，如 FREQ=DAILY;COUNT=3
+                name if name.contains('=') => rrule.push(parse_rrule(line)?),
+                _ => other_lines.push(line.to_string()),
             }
-            rrule = RRule::from_str(lines[1]);
-        } else {
-            rrule = RRule::from_str(lines[0]);
         }
 
+        // RDATE、EXDATE的tz可能与DTSTART不同，统一转换到tz下
+        // 有TZID时按TZID下的当地时间处理，否则带Z的是UTC时间，不带Z的是tz下的当地时间
+        let parse_date_lines = |lines: Vec<&str>| -> Result<Vec<PointTime>, RRuleError> {
+            let mut dates: Vec<PointTime> = vec![];
+            for line in lines {
+                let (list, line_tz) = parse_date_list_str_and_tz(line, timezones)?;
+                for n in list.iter() {
+                    let (time, form) = match (&line_tz, n.form) {
+                        (_, TimeForm::Date) => (n.clone(), TimeForm::Date),
+                        (Some(line_tz), _) => (n.convert_timezone(line_tz, &tz), TimeForm::Utc),
+                        (None, TimeForm::Utc) => {
+                            (n.convert_timezone(&Timezone::UTC, &tz), TimeForm::Utc)
+                        }
+                        (None, form) => (n.clone(), form),
+                    };
+                    dates.push(Self::form_in(&tz, &time.with_form(form)));
+                }
+            }
+            Ok(dates)
+        };
+        let rdate = parse_date_lines(rdate_lines)?;
+        let exdate = parse_date_lines(exdate_lines)?;
+
         Ok(RRuleSet {
-            rrule: vec![rrule],
+            rrule,
+            exrule,
             tz,
-            start_point_time: start_point_time,
-            max_until_time: constant::MAX_UNTIL_STR.parse::<PointTime>().unwrap(),
+            start_point_time,
+            rdate,
+            exdate,
+            other_lines,
             between_start: None,
             between_end: None,
+            dst_policy: DstPolicy::default(),
+            options: ExpandOptions::default(),
         })
     }
-    pub fn add_rrule(&mut self, rrule: &str) {
-        self.rrule.push(RRule::from_str(rrule))
+    pub fn add_rrule(&mut self, rrule: &str) -> Result<(), RRuleError> {
+        self.rrule.push(RRule::from_str(rrule)?);
+        Ok(())
     }
 
-    pub fn set_dt_start(&mut self, str: &str) {
-        let time = str.parse::<PointTime>();
-        if let Ok(point_time) = time {
-            self.start_point_time = Some(point_time);
-        }
+    pub fn add_exrule(&mut self, exrule: &str) -> Result<(), RRuleError> {
+        self.exrule.push(RRule::from_str(exrule)?);
+        Ok(())
     }
 
-    pub fn tz(&mut self, tz: &str) {
-        self.tz = get_tz_from_str(tz)
+    /// add rdate in tz, a time with `Z` is converted from UTC
+    pub fn add_rdate(&mut self, str: &str) -> Result<(), RRuleError> {
+        let point_time = self.parse_in_tz(str)?;
+        if !self.rdate.contains(&point_time) {
+            self.rdate.push(point_time);
+        }
+        Ok(())
     }
 
-    pub fn set_count(&mut self, count: u32) {
-        if self.rrule.len() == 0 {
-            return;
+    /// add exdate in tz, a time with `Z` is converted from UTC
+    pub fn add_exdate(&mut self, str: &str) -> Result<(), RRuleError> {
+        let point_time = self.parse_in_tz(str)?;
+        if !self.exdate.contains(&point_time) {
+            self.exdate.push(point_time);
         }
-        self.rrule.get_mut(0).unwrap().set_count(count);
+        Ok(())
     }
 
-    pub fn set_until(&mut self, str: &str) {
-        if self.rrule.len() == 0 {
-            return;
-        }
-        self.rrule.get_mut(0).unwrap().set_until(str);
+    /// remove exdate which is equal to the str, read the same way as `add_exdate`
+    pub fn remove_exdate(&mut self, str: &str) -> Result<(), RRuleError> {
+        let point_time = self.parse_in_tz(str)?;
+        self.exdate.retain(|n| n != &point_time);
+        Ok(())
     }
 
-    /// set between range, used when return the list in all func
-    /// filter list which >= between_start and <= between_end
-    pub fn between(&mut self, start: &str, end: &str) {
-        let between_start: Result<PointTime, _> = start.parse();
-        let between_end: Result<PointTime, _> = end.parse();
-        if between_start.is_ok() {
-            self.between_start = Some(between_start.unwrap());
-        }
-        if between_end.is_ok() {
-            self.between_end = Some(between_end.unwrap());
-        }
+    pub fn set_dt_start(&mut self, str: &str) -> Result<(), RRuleError> {
+        self.start_point_time = Some(self.parse_in_tz(str)?);
+        Ok(())
+    }
+
+    pub fn tz(&mut self, tz: &str) -> Result<(), RRuleError> {
+        self.set_timezone(Timezone::Tz(get_tz_from_str(tz)?));
+        Ok(())
     }
 
-    pub fn all(&self) -> Vec<DateTime<Tz>> {
-        if self.start_point_time.is_none() {
-            return Vec::new();
+    /// set the timezone, which can be a custom timezone built from a VTIMEZONE
+    pub fn set_timezone(&mut self, tz: Timezone) {
+        self.tz = tz;
+        // 时间的值不变，作为新时区下的当地时间
+        let tz = &self.tz;
+        self.start_point_time = self.start_point_time.as_ref().map(|n| Self::form_in(tz, n));
+        for time in self.rdate.iter_mut().chain(self.exdate.iter_mut()) {
+            *time = Self::form_in(tz, time);
         }
+    }
 
-        if self.rrule.len() == 0 {
-            return Vec::new();
+    /// 解析传入的时间，与RDATE、EXDATE行相同，带Z的是UTC时间，转换到tz下，否则是tz下的当地时间
+    fn parse_in_tz(&self, str: &str) -> Result<PointTime, RRuleError> {
+        let time = str.parse::<PointTime>()?;
+        let time = match time.form {
+            TimeForm::Utc => time
+                .convert_timezone(&Timezone::UTC, &self.tz)
+                .with_form(TimeForm::Utc),
+            _ => time,
+        };
+        Ok(Self::form_in(&self.tz, &time))
+    }
+
+    /// tz下的时间的写法，日期不变，不是UTC时为Zoned，是UTC时Zoned改为Utc，Floating不变
+    pub(crate) fn form_in(tz: &Timezone, time: &PointTime) -> PointTime {
+        match time.form {
+            TimeForm::Date => time.clone(),
+            _ if *tz != Tz::UTC => time.with_form(TimeForm::Zoned),
+            TimeForm::Zoned => time.with_form(TimeForm::Utc),
+            _ => time.clone(),
         }
+    }
 
-        // 只要长度不为0，就一定有值
-        let rrule = self.rrule.get(0).unwrap();
+    /// 输出时使用的规则，按RFC 5545，DTSTART为日期时UNTIL也为日期，有时区时UNTIL为UTC时间，否则与DTSTART的写法相同
+    pub(crate) fn rrule_for_output(&self, rrule: &RRule) -> RRule {
+        let mut rrule = rrule.clone();
+        let start_form = self.start_point_time.as_ref().map(|n| n.form);
+        rrule.until = rrule.until.map(|until| match until.form {
+            _ if start_form == Some(TimeForm::Date) => until.with_form(TimeForm::Date),
+            TimeForm::Utc | TimeForm::Date => until,
+            _ if self.tz != Tz::UTC => until
+                .convert_timezone(&self.tz, &Tz::UTC)
+                .with_form(TimeForm::Utc),
+            _ => until.with_form(start_form.unwrap_or(until.form)),
+        });
+        rrule
+    }
 
-        // 如果没设置长度和截止时间，直接返回[]
-        if rrule.count == 0 && rrule.until.is_none() {
-            return Vec::new();
+    /// set how to convert the local times which do not exist or are ambiguous because of the daylight saving time
+    pub fn set_dst_policy(&mut self, policy: DstPolicy) {
+        self.dst_policy = policy;
+    }
+
+    /// set the limits of the expansion, see [`ExpandOptions`]
+    pub fn set_options(&mut self, options: ExpandOptions) {
+        self.options = options;
+    }
+
+    /// set count of the first rrule
+    pub fn set_count(&mut self, count: u32) {
+        self.set_rrule_count(0, count);
+    }
+
+    /// set until of the first rrule
+    pub fn set_until(&mut self, str: &str) -> Result<(), RRuleError> {
+        self.set_rrule_until(0, str)
+    }
+
+    /// set count of the rrule at index, ignored if the index is out of range
+    pub fn set_rrule_count(&mut self, index: usize, count: u32) {
+        if let Some(rrule) = self.rrule.get_mut(index) {
+            rrule.set_count(count);
         }
+    }
 
-        // 如果长度为0，并且开始时间大于截止时间，直接返回[]
-        if rrule.count == 0 {
-            let start_point_time = self.start_point_time.as_ref().unwrap();
-            let end_point_time = rrule.until.as_ref().unwrap();
-            if start_point_time > end_point_time {
-                return vec![];
-            }
+    /// set until of the rrule at index, ignored if the index is out of range
+    pub fn set_rrule_until(&mut self, index: usize, str: &str) -> Result<(), RRuleError> {
+        match self.rrule.get_mut(index) {
+            Some(rrule) => rrule.set_until(str),
+            None => Ok(()),
         }
+    }
 
-        // todo 提前排除下week_no与by_month\by_year_day的交集是否有效
+    /// set between range, used when return the list in all func
+    /// filter list which >= between_start and <= between_end, compared with the full date-time.
+    /// The times are in the timezone of the set, or in UTC when they end with `Z`
+    pub fn set_between(&mut self, start: &str, end: &str) -> Result<(), RRuleError> {
+        let between_start = start.parse::<PointTime>()?;
+        let between_end = end.parse::<PointTime>()?;
+        self.between_start = Some(between_start);
+        self.between_end = Some(between_end);
+        Ok(())
+    }
 
-        let list: Vec<PointTime> = match rrule.freq {
-            crate::rrule::Frequency::Yearly => self.expand_by_year().unwrap(),
-            crate::rrule::Frequency::Monthly => self.expand_by_month().unwrap(),
-            crate::rrule::Frequency::Weekly => self.expand_by_week().unwrap(),
-            crate::rrule::Frequency::Daily => self.expand_by_day().unwrap(),
-            crate::rrule::Frequency::Hourly => todo!(),
-            crate::rrule::Frequency::Minutely => todo!(),
-            crate::rrule::Frequency::Secondly => todo!(),
-        };
+    /// tz下的时刻，UTC写法的时间先按UTC时间转换
+    pub(crate) fn to_date_time(&self, time: &PointTime) -> DateTime<Timezone> {
+        match time.form {
+            TimeForm::Utc => time.with_timezone(&Timezone::UTC).with_timezone(&self.tz),
+            _ => time.with_timezone(&self.tz),
+        }
+    }
 
-        list.into_iter()
-            .filter(|n| {
-                if self.between_start.is_none() {
-                    true
-                } else {
-                    n >= &self.between_start.as_ref().unwrap()
-                }
-            })
-            .filter(|n| {
-                if self.between_end.is_none() {
-                    true
-                } else {
-                    n <= &self.between_end.as_ref().unwrap()
-                }
-            })
-            .map(|p| p.with_timezone(&self.tz))
-            .collect()
+    /// Returns the first occurrence after `dt`, `dt` is in the timezone of the set, or in UTC when it ends with `Z`.
+    /// With `inclusive` the occurrence equal to `dt` is also returned.
+    pub fn after(&self, dt: &str, inclusive: bool) -> Result<Option<DateTime<Timezone>>, RRuleError> {
+        let dt = self.to_date_time(&dt.parse::<PointTime>()?);
+        Ok(self
+            .iter()
+            .find(|time| time > &dt || (inclusive && time == &dt)))
     }
 
-    /// 按天扩展，无效则报错
-    fn expand_by_day(&self) -> Result<Vec<PointTime>, String> {
-        let point_time = self.start_point_time.as_ref().unwrap();
-        let rrule = self.rrule.get(0).unwrap();
-        let interval = rrule.interval;
-        let end_time = if let Some(until) = &rrule.until {
-            &until
-        } else {
-            &self.max_until_time
-        };
-        let max = if rrule.count == 0 {
-            65535
-        } else {
-            rrule.count as usize
-        };
-        let by_day_every = rrule
-            .by_day
+    /// Returns the last occurrence before `dt`, `dt` is in the timezone of the set, or in UTC when it ends with `Z`.
+    /// With `inclusive` the occurrence equal to `dt` is also returned.
+    pub fn before(&self, dt: &str, inclusive: bool) -> Result<Option<DateTime<Timezone>>, RRuleError> {
+        let dt = self.to_date_time(&dt.parse::<PointTime>()?);
+        Ok(self
             .iter()
-            .map(|n| n.get_weekday().clone())
-            .collect::<Vec<Weekday>>();
-        let naive_dt_start =
-            NaiveDate::from_ymd_opt(point_time.year, point_time.month, point_time.day).unwrap();
-        let naive_end_time =
-            NaiveDate::from_ymd_opt(end_time.year, end_time.month, end_time.day).unwrap();
-
-        let mut next = naive_dt_start.clone();
-        let mut list: Vec<NaiveDate> = Vec::new();
-
-        let go_step = |time: NaiveDate| {
-            return time + Duration::days(interval.into());
-        };
+            .take_while(|time| time < &dt || (inclusive && time == &dt))
+            .last())
+    }
 
-        while next <= naive_end_time && list.len() < max {
-            if !by_day_every.is_empty() && !by_day_every.contains(&next.weekday()) {
-                next = go_step(next);
-                continue;
-            }
-            if !rrule.by_month.is_empty() && !rrule.by_month.contains(&(next.month() as u8)) {
-                next = go_step(next);
-                continue;
-            }
-            if !rrule.by_month_day.is_empty() {
-                let mut flag = false;
-                for day in rrule.by_month_day.iter() {
-                    if Self::is_nth_day_of_month(&next, *day) {
-                        flag = true;
-                        break;
-                    }
-                }
-                if !flag {
-                    next = go_step(next);
-                    continue;
-                }
-            }
-            if !rrule.by_year_day.is_empty() {
-                let mut flag = false;
-                for day in rrule.by_year_day.iter() {
-                    if Self::is_nth_day_of_year(&next, *day) {
-                        flag = true;
-                        break;
-                    }
-                }
-                if !flag {
-                    next = go_step(next);
-                    continue;
-                }
-            }
-            if !rrule.by_week_no.is_empty() {
-                let mut flag = false;
-                for day in rrule.by_week_no.iter() {
-                    if Self::is_in_nth_weekno(&next, *day) {
-                        flag = true;
-                        break;
-                    }
-                }
-                if !flag {
-                    next = go_step(next);
-                    continue;
-                }
-            }
-            list.push(next.clone());
-            next = go_step(next);
-        }
+    /// Returns all the occurrences between `start` and `end`, both are in the timezone of the set, or in UTC when they end with `Z`.
+    /// With `inclusive` the occurrences equal to `start` or `end` are also returned.
+    pub fn between(
+        &self,
+        start: &str,
+        end: &str,
+        inclusive: bool,
+    ) -> Result<Vec<DateTime<Timezone>>, RRuleError> {
+        let start = self.to_date_time(&start.parse::<PointTime>()?);
+        let end = self.to_date_time(&end.parse::<PointTime>()?);
+        Ok(self
+            .iter()
+            .skip_while(|time| time < &start || (!inclusive && time == &start))
+            .take_while(|time| time < &end || (inclusive && time == &end))
+            .collect())
+    }
 
-        Ok(list
-            .into_iter()
-            .map(|n| PointTime {
-                year: n.year(),
-                month: n.month(),
-                day: n.day(),
-                hour: point_time.hour,
-                min: point_time.min,
-                sec: point_time.sec,
-            })
-            .collect::<Vec<PointTime>>())
+    /// Returns all the occurrences, at most `max_results` of the [`ExpandOptions`].
+    /// A rule without count and until never ends, use `set_between`, `all_with_limit` or `set_options` to bound it.
+    pub fn all(&self) -> Occurrences {
+        self.all_with_limit(self.options.max_results)
     }
 
-    /// 按周扩展，无效则报错
-    fn expand_by_week(&self) -> Result<Vec<PointTime>, String> {
-        let point_time = self.start_point_time.as_ref().unwrap();
-        let rrule = self.rrule.get(0).unwrap();
-        let interval = rrule.interval;
-        let end_time = if let Some(until) = &rrule.until {
-            &until
-        } else {
-            &self.max_until_time
-        };
-        let max = if rrule.count == 0 {
-            65535
-        } else {
-            rrule.count as usize
-        };
+    /// Returns the first `limit` occurrences, `truncated` is true when there are more,
+    /// or the expansion stops early because of the [`ExpandOptions`]
+    pub fn all_with_limit(&self, limit: usize) -> Occurrences {
+        let mut iter = self.iter();
+        let list = iter.by_ref().take(limit).collect::<Vec<_>>();
+        // 多取一个，判断是否还有更多的时间
+        let truncated = (list.len() == limit && iter.next().is_some()) || iter.is_truncated();
+        Occurrences { list, truncated }
+    }
 
-        let naive_dt_start =
-            NaiveDate::from_ymd_opt(point_time.year, point_time.month, point_time.day).unwrap();
-        let naive_end_time =
-            NaiveDate::from_ymd_opt(end_time.year, end_time.month, end_time.day).unwrap();
-        let dt_start_weekday = naive_dt_start.weekday();
+    /// Whether the set is all-day, i.e. `DTSTART` is a date like `DTSTART;VALUE=DATE:20231115`,
+    /// or there is no `DTSTART` and all the `RDATE` are dates
+    pub fn is_all_day(&self) -> bool {
+        match &self.start_point_time {
+            Some(start) => start.form == TimeForm::Date,
+            None => !self.rdate.is_empty() && self.rdate.iter().all(|n| n.form == TimeForm::Date),
+        }
+    }
 
-        let binding = vec![dt_start_weekday.clone()];
-        let by_day_every = if rrule.by_day.len() == 0 {
-            binding
-        } else {
-            rrule
-                .by_day
-                .iter()
-                .map(|n| n.get_weekday().clone())
-                .collect::<Vec<Weekday>>()
-        };
+    /// Returns the dates of the occurrences, at most `max_results` of the [`ExpandOptions`].
+    /// The occurrences of an all-day set are at 00:00 in the timezone of the set, see `is_all_day`
+    pub fn all_dates(&self) -> Vec<NaiveDate> {
+        self.all().iter().map(|n| n.date_naive()).collect()
+    }
 
-        let mut next = naive_dt_start.clone();
-        let mut list: Vec<NaiveDate> = Vec::new();
+    /// Returns a lazy iterator of the occurrences in order, without duplicates.
+    /// The occurrences are generated period by period, only when needed.
+    pub fn iter(&self) -> RRuleSetIter<'_> {
+        RRuleSetIter::new(self)
+    }
 
-        let go_step = |time: NaiveDate| {
-            let weekday = time.weekday();
-            return if weekday == rrule.week_start.pred() && interval != 1 {
-                time + Duration::weeks((interval - 1).into()) + Duration::days(1)
-            } else {
-                time + Duration::days(1)
-            };
+    /// 展开freq的一个周期，返回周期内所有满足条件的时间，未按dt_start和until过滤
+    fn expand_period(
+        rrule: &RRule,
+        dt_start: &NaiveDateTime,
+        period: &NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        let dates = match rrule.freq {
+            Frequency::Yearly => Self::expand_by_year(rrule, dt_start, period.year()),
+            Frequency::Monthly => Self::expand_by_month(rrule, dt_start, &period.date()),
+            Frequency::Weekly => Self::expand_by_week(rrule, dt_start, &period.date()),
+            Frequency::Daily => Self::expand_by_day(rrule, &period.date()),
+            // HOURLY、MINUTELY、SECONDLY的周期小于一天，只需判断日期是否满足
+            Frequency::Hourly | Frequency::Minutely | Frequency::Secondly => {
+                if Self::is_date_matched(rrule, &period.date()) {
+                    return Self::get_times_of_day(rrule, period);
+                }
+                return vec![];
+            }
         };
+        dates
+            .into_iter()
+            .flat_map(|n| Self::get_times_of_day(rrule, &n.and_time(dt_start.time())))
+            .collect()
+    }
 
-        while next <= naive_end_time && list.len() < max {
-            let weekday = next.weekday();
-            if !by_day_every.contains(&weekday) {
-                next = go_step(next);
-                continue;
+    /// 获取下一个周期的开始，超出范围时返回None
+    /// - YEARLY、MONTHLY、WEEKLY从下一个周期的第一天开始
+    /// - HOURLY、MINUTELY、SECONDLY在日期不满足过滤条件时，直接跳到下一天的第一个步长点，避免逐秒遍历
+    fn get_next_period(rrule: &RRule, period: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let interval = rrule.interval.max(1);
+        let date = period.date();
+        let interval_i64 = i64::from(interval);
+        let next = match rrule.freq {
+            Frequency::Yearly => {
+                NaiveDate::from_ymd_opt(date.year().checked_add(i32::try_from(interval).ok()?)?, 1, 1)?
             }
-            if !rrule.by_month.is_empty() && !rrule.by_month.contains(&(next.month() as u8)) {
-                next = go_step(next);
-                continue;
+            Frequency::Monthly => date.with_day(1)?.checked_add_months(Months::new(interval))?,
+            Frequency::Weekly => {
+                Self::get_week_start(rrule, &date).checked_add_signed(Duration::weeks(interval_i64))?
             }
-            if !rrule.by_month_day.is_empty() {
-                let mut flag = false;
-                for day in rrule.by_month_day.iter() {
-                    if Self::is_nth_day_of_month(&next, *day) {
-                        flag = true;
-                        break;
-                    }
-                }
-                if !flag {
-                    next = go_step(next);
-                    continue;
+            Frequency::Daily => date.checked_add_signed(Duration::days(interval_i64))?,
+            Frequency::Hourly | Frequency::Minutely | Frequency::Secondly => {
+                let unit: i64 = match rrule.freq {
+                    Frequency::Hourly => 3600,
+                    Frequency::Minutely => 60,
+                    _ => 1,
+                };
+                // 步长用i64计算，u32的interval乘以3600也不会溢出
+                let step_seconds = unit * interval_i64;
+                if Self::is_date_matched(rrule, &date) {
+                    return period.checked_add_signed(Duration::seconds(step_seconds));
                 }
+                // 跳过当天剩余的时间，保持与dt_start的步长对齐
+                let next_day = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+                let steps = ((next_day - *period).num_seconds() + step_seconds - 1) / step_seconds;
+                return period.checked_add_signed(Duration::seconds(steps.checked_mul(step_seconds)?));
             }
-            if !rrule.by_year_day.is_empty() {
-                let mut flag = false;
-                for day in rrule.by_year_day.iter() {
-                    if Self::is_nth_day_of_year(&next, *day) {
-                        flag = true;
-                        break;
-                    }
-                }
-                if !flag {
-                    next = go_step(next);
-                    continue;
-                }
-            }
-            if !rrule.by_week_no.is_empty() {
-                let mut flag = false;
-                for day in rrule.by_week_no.iter() {
-                    if Self::is_in_nth_weekno(&next, *day) {
-                        flag = true;
-                        break;
-                    }
-                }
-                if !flag {
-                    next = go_step(next);
-                    continue;
-                }
-            }
-            list.push(next.clone());
-            next = go_step(next);
+        };
+        next.and_hms_opt(0, 0, 0)
+    }
+
+    /// 按天扩展一个周期
+    fn expand_by_day(rrule: &RRule, date: &NaiveDate) -> Vec<NaiveDate> {
+        if Self::is_date_matched(rrule, date) {
+            return vec![*date];
         }
+        vec![]
+    }
 
-        Ok(list
-            .into_iter()
-            .map(|n| PointTime {
-                year: n.year(),
-                month: n.month(),
-                day: n.day(),
-                hour: point_time.hour,
-                min: point_time.min,
-                sec: point_time.sec,
-            })
-            .collect::<Vec<PointTime>>())
+    /// 按周扩展一个周期，从给定日期开始到这一周结束
+    /// 第一周从dt_start开始，与rrule.js保持一致
+    fn expand_by_week(rrule: &RRule, dt_start: &NaiveDateTime, date: &NaiveDate) -> Vec<NaiveDate> {
+        let week_end = Self::get_week_start(rrule, date) + Duration::days(6);
+        date.iter_days()
+            .take_while(|n| n <= &week_end)
+            // 未设置BYDAY时，只取dt_start所在的星期几
+            .filter(|n| !rrule.by_day.is_empty() || n.weekday() == dt_start.weekday())
+            .filter(|n| Self::is_date_matched(rrule, n))
+            .collect()
     }
 
-    /// 按月扩展，无效则报错
+    /// 获取给定日期所在周的第一天，按WKST计算
+    fn get_week_start(rrule: &RRule, date: &NaiveDate) -> NaiveDate {
+        let days = (7 + date.weekday().num_days_from_monday()
+            - rrule.week_start.num_days_from_monday())
+            % 7;
+        *date - Duration::days(days.into())
+    }
+
+    /// 按月扩展一个周期，返回当月所有满足条件的日期
     /// - 先判断是否有bymonthday，有则直接用，并且确认是否在byday中
     /// - 然后看有没有byday，迭代byday，如果是普通weekday，则取1..5来获取对应周数的时间，有则push到dates中，指定周数的日期，则指定处理
-    /// 如果没有byday，则只需从开始时间起，按月累加即可，直到大于截止时间或者超出limit限制。
-
-    fn expand_by_month(&self) -> Result<Vec<PointTime>, String> {
-        let point_time = self.start_point_time.as_ref().unwrap();
-        let rrule = self.rrule.get(0).unwrap();
-        let limit = rrule.count;
-        let interval = rrule.interval;
-        let end_time = if let Some(until) = &rrule.until {
-            &until
-        } else {
-            &self.max_until_time
-        };
-        let max = if limit == 0 { 65535 } else { limit as usize };
-
-        let naive_dt_start =
-            NaiveDate::from_ymd_opt(point_time.year, point_time.month, point_time.day).unwrap();
-        let naive_end_time =
-            NaiveDate::from_ymd_opt(end_time.year, end_time.month, end_time.day).unwrap();
-
+    /// - 如果没有byday等限制，则只取dt_start对应的那一天
+    fn expand_by_month(rrule: &RRule, dt_start: &NaiveDateTime, curr: &NaiveDate) -> Vec<NaiveDate> {
         // 存粹按月循环
         if rrule.by_month_day.is_empty()
             && rrule.by_year_day.is_empty()
-            && rrule.by_month_day.is_empty()
             && rrule.by_week_no.is_empty()
             && rrule.by_day.is_empty()
         {
-            let mut dates: Vec<PointTime> = Vec::new();
-            let mut next: PointTime = point_time.clone();
-
-            while &next <= end_time && dates.len() < max {
-                dates.push(next.clone());
-                next = next.add_month(interval);
-            }
-
-            return Ok(dates);
-        }
-
-        let generate_dates_in_month = |curr: &NaiveDate| -> Vec<NaiveDate> {
-            // 不符合月份直接打回
-            if !rrule.by_month.is_empty() && rrule.by_month.contains(&(curr.month() as u8)) {
+            if !rrule.by_month.is_empty() && !rrule.by_month.contains(&(curr.month() as u8)) {
                 return vec![];
             }
+            return NaiveDate::from_ymd_opt(curr.year(), curr.month(), dt_start.day())
+                .into_iter()
+                .collect();
+        }
 
-            // 先缓存符合一定条件的，再过滤不符合另一部分条件的
-            let mut list: Vec<NaiveDate> = vec![];
-            let curr_month_start = NaiveDate::from_ymd_opt(curr.year(), curr.month(), 1).unwrap();
-            let curr_month_end = Self::get_last_day_of_month(curr.year(), curr.month());
+        // 不符合月份直接打回
+        if !rrule.by_month.is_empty() && !rrule.by_month.contains(&(curr.month() as u8)) {
+            return vec![];
+        }
 
-            let valid_start = if curr_month_start > naive_dt_start {
-                &curr_month_start
-            } else {
-                &naive_dt_start
-            };
-            let valid_end = if curr_month_end > naive_end_time {
-                &naive_end_time
-            } else {
-                &curr_month_end
-            };
+        // 先缓存符合一定条件的，再过滤不符合另一部分条件的
+        let mut list: Vec<NaiveDate> = vec![];
+        let (Some(curr_month_start), Some(curr_month_end)) = (
+            curr.with_day(1),
+            Self::get_last_day_of_month(curr.year(), curr.month()),
+        ) else {
+            return vec![];
+        };
 
-            // 如果指定了yearday但是无结果，则直接返回，如果有，push到list里
-            if !rrule.by_year_day.is_empty() {
-                // 先生成所有的year_day
-                let vec_by_year_day = rrule
-                    .by_year_day
-                    .iter()
-                    .map(|day| Self::get_nth_day_of_year(curr.year(), *day))
-                    .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
-                    .map(|n| n.unwrap())
-                    .collect::<Vec<NaiveDate>>();
+        // BYSETPOS作用于整个周期，这里不按dt_start和until截取，放入结果时再过滤
+        let valid_start = &curr_month_start;
+        let valid_end = &curr_month_end;
 
-                if vec_by_year_day.is_empty() {
-                    return vec![];
-                }
-                vec_by_year_day.into_iter().for_each(|n| list.push(n));
-            };
+        // 如果指定了yearday但是无结果，则直接返回，如果有，push到list里
+        if !rrule.by_year_day.is_empty() {
+            // 先生成所有的year_day
+            let vec_by_year_day = rrule
+                .by_year_day
+                .iter()
+                .map(|day| Self::get_nth_day_of_year(curr.year(), *day))
+                .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+                .map(|n| n.unwrap())
+                .collect::<Vec<NaiveDate>>();
 
-            // 如果指定了bymonthday但是无结果，则直接返回
-            // 如果有值，则判断与list是做交集还是直接塞入
-            // 运算完后还是没有，则直接返回
-            if !rrule.by_month_day.is_empty() {
-                let vec_by_month_day = rrule
-                    .by_month_day
-                    .iter()
-                    .map(|month_day| {
-                        Self::get_nth_day_of_month(curr.year(), curr.month(), *month_day)
-                    })
-                    .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
-                    .map(|n| n.unwrap())
-                    .collect::<Vec<NaiveDate>>();
+            if vec_by_year_day.is_empty() {
+                return vec![];
+            }
+            vec_by_year_day.into_iter().for_each(|n| list.push(n));
+        };
 
-                if vec_by_month_day.is_empty() {
-                    return vec![];
-                }
-                if !list.is_empty() {
-                    list = list
-                        .into_iter()
-                        .filter(|n| vec_by_month_day.contains(&n))
-                        .collect::<Vec<NaiveDate>>();
-                } else {
-                    vec_by_month_day.into_iter().for_each(|n| list.push(n));
-                }
-                if list.is_empty() {
-                    return vec![];
-                }
-            };
+        // 如果指定了bymonthday但是无结果，则直接返回
+        // 如果有值，则判断与list是做交集还是直接塞入
+        // 运算完后还是没有，则直接返回
+        if !rrule.by_month_day.is_empty() {
+            let vec_by_month_day = rrule
+                .by_month_day
+                .iter()
+                .map(|month_day| {
+                    Self::get_nth_day_of_month(curr.year(), curr.month(), *month_day)
+                })
+                .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+                .map(|n| n.unwrap())
+                .collect::<Vec<NaiveDate>>();
 
-            // week_no只能跟by_day交集
-            if !rrule.by_week_no.is_empty() {
-                let vec_by_week_no = rrule
-                    .by_week_no
-                    .iter()
-                    .map(|week_no| {
-                        return Self::get_all_weekday()
-                            .into_iter()
-                            .map(|_| Self::get_nth_week_by_week_no(curr.year(), *week_no))
-                            .filter(|n| n.is_some())
-                            .map(|n| {
-                                let first = &n.unwrap();
-                                return (0..6)
-                                    .map(|num| *first + Duration::days(num))
-                                    .collect::<Vec<NaiveDate>>();
-                            })
-                            .flatten()
-                            .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
-                            .collect::<Vec<NaiveDate>>();
-                    })
-                    .flatten()
+            if vec_by_month_day.is_empty() {
+                return vec![];
+            }
+            if !list.is_empty() {
+                list = list
+                    .into_iter()
+                    .filter(|n| vec_by_month_day.contains(&n))
                     .collect::<Vec<NaiveDate>>();
+            } else {
+                vec_by_month_day.into_iter().for_each(|n| list.push(n));
+            }
+            if list.is_empty() {
+                return vec![];
+            }
+        };
 
-                if !list.is_empty() {
-                    list = list
+        // week_no只能跟by_day交集
+        if !rrule.by_week_no.is_empty() {
+            let vec_by_week_no = rrule
+                .by_week_no
+                .iter()
+                .map(|week_no| {
+                    return Self::get_all_weekday()
                         .into_iter()
-                        .filter(|n| vec_by_week_no.contains(&n))
+                        .map(|_| Self::get_nth_week_by_week_no(curr.year(), *week_no))
+                        .filter(|n| n.is_some())
+                        .map(|n| {
+                            let first = &n.unwrap();
+                            return (0..6)
+                                .map(|num| *first + Duration::days(num))
+                                .collect::<Vec<NaiveDate>>();
+                        })
+                        .flatten()
+                        .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
                         .collect::<Vec<NaiveDate>>();
-                } else {
-                    vec_by_week_no.into_iter().for_each(|n| list.push(n));
-                }
-                if list.is_empty() {
-                    return vec![];
-                }
-            };
+                })
+                .flatten()
+                .collect::<Vec<NaiveDate>>();
 
-            if !rrule.by_day.is_empty() {
-                let vec_by_day = rrule
-                    .by_day
-                    .iter()
-                    .map(|n_weekday| match n_weekday {
-                        NWeekday::Every(weekday) => {
-                            Self::get_all_weekday_of_month(curr.year(), curr.month(), weekday)
-                        }
-                        NWeekday::Nth(_, _) => vec![Self::get_nth_weekday_of_month(
-                            curr.year(),
-                            curr.month(),
-                            n_weekday.get_weekday(),
-                            n_weekday.get_nth(),
-                        )]
-                        .iter()
-                        .filter(|n| n.is_some())
-                        .map(|n| n.unwrap())
-                        .collect::<Vec<NaiveDate>>(),
-                    })
-                    .flatten()
-                    .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
+            if !list.is_empty() {
+                list = list
+                    .into_iter()
+                    .filter(|n| vec_by_week_no.contains(&n))
                     .collect::<Vec<NaiveDate>>();
-
-                if !list.is_empty() {
-                    list = list
-                        .into_iter()
-                        .filter(|n| vec_by_day.contains(&n))
-                        .collect::<Vec<NaiveDate>>();
-                } else {
-                    vec_by_day.into_iter().for_each(|n| list.push(n));
-                }
-                if list.is_empty() {
-                    return vec![];
-                }
+            } else {
+                vec_by_week_no.into_iter().for_each(|n| list.push(n));
+            }
+            if list.is_empty() {
+                return vec![];
             }
-            list.sort();
-            return list;
         };
 
-        let mut next =
-            NaiveDate::from_ymd_opt(naive_dt_start.year(), naive_dt_start.month(), 1).unwrap();
-        let mut list: Vec<PointTime> = vec![];
-
-        while list.len() < max && next < naive_end_time {
-            let rs = generate_dates_in_month(&next);
-            rs.into_iter().for_each(|n| {
-                list.push(PointTime {
-                    year: n.year(),
-                    month: n.month(),
-                    day: n.day(),
-                    hour: point_time.hour,
-                    min: point_time.min,
-                    sec: point_time.sec,
+        if !rrule.by_day.is_empty() {
+            let vec_by_day = rrule
+                .by_day
+                .iter()
+                .map(|n_weekday| match n_weekday {
+                    NWeekday::Every(weekday) => {
+                        Self::get_all_weekday_of_month(curr.year(), curr.month(), weekday)
+                    }
+                    NWeekday::Nth(_, _) => vec![Self::get_nth_weekday_of_month(
+                        curr.year(),
+                        curr.month(),
+                        n_weekday.get_weekday(),
+                        n_weekday.get_nth(),
+                    )]
+                    .iter()
+                    .filter(|n| n.is_some())
+                    .map(|n| n.unwrap())
+                    .collect::<Vec<NaiveDate>>(),
                 })
-            });
-            next = next.checked_add_months(Months::new(interval)).unwrap();
+                .flatten()
+                .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
+                .collect::<Vec<NaiveDate>>();
+
+            if !list.is_empty() {
+                list = list
+                    .into_iter()
+                    .filter(|n| vec_by_day.contains(&n))
+                    .collect::<Vec<NaiveDate>>();
+            } else {
+                vec_by_day.into_iter().for_each(|n| list.push(n));
+            }
+            if list.is_empty() {
+                return vec![];
+            }
         }
-        let len = if list.len() < max { list.len() } else { max };
-        list = list[0..len].to_vec();
         list.sort();
-
-        Ok(list)
+        return list;
     }
 
-    fn expand_by_year(&self) -> Result<Vec<PointTime>, String> {
-        let point_time = self.start_point_time.as_ref().unwrap();
-        let rrule = self.rrule.get(0).unwrap();
-        let max = if rrule.count == 0 {
-            65535
-        } else {
-            rrule.count as usize
-        };
-        let interval = rrule.interval;
-        let end_time = if let Some(until) = &rrule.until {
-            &until
-        } else {
-            &self.max_until_time
-        };
-
-        let naive_dt_start =
-            NaiveDate::from_ymd_opt(point_time.year, point_time.month, point_time.day).unwrap();
-        let naive_end_time =
-            NaiveDate::from_ymd_opt(end_time.year, end_time.month, end_time.day).unwrap();
+    /// 按年扩展一个周期，返回当年所有满足条件的日期
+    fn expand_by_year(rrule: &RRule, dt_start: &NaiveDateTime, curr_year: i32) -> Vec<NaiveDate> {
+        let curr_year_start = NaiveDate::from_ymd_opt(curr_year, 1, 1).unwrap();
+        let curr_year_end = Self::get_last_day_of_year(curr_year);
 
-        //
-        let generate_by_year = |curr_year: i32| -> Vec<NaiveDate> {
-            let curr_year_start = NaiveDate::from_ymd_opt(curr_year, 1, 1).unwrap();
-            let curr_year_end = Self::get_last_day_of_year(curr_year);
+        // BYSETPOS作用于整个周期，这里不按dt_start和until截取，放入结果时再过滤
+        let valid_start = &curr_year_start;
+        let valid_end = &curr_year_end;
 
-            let valid_start = if curr_year_start > naive_dt_start {
-                &curr_year_start
+        // 只有BYMONTH的时候不会降级，还是执行按年重复，符合条件可以提前退出
+        if rrule.by_year_day.is_empty()
+            && rrule.by_month_day.is_empty()
+            && rrule.by_week_no.is_empty()
+            && rrule.by_day.is_empty()
+        {
+            let bindings = vec![dt_start.month() as u8];
+            return (if rrule.by_month.is_empty() {
+                &bindings
             } else {
-                &naive_dt_start
-            };
-            let valid_end = if curr_year_end > naive_end_time {
-                &curr_year_end
+                &rrule.by_month
+            })
+            .iter()
+            .map(|month| {
+                NaiveDate::from_ymd_opt(curr_year, *month as u32, dt_start.day())
+            })
+            .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+            .map(|n| n.unwrap())
+            .collect::<Vec<NaiveDate>>();
+        }
+
+        // 先缓存符合一定条件的，再过滤不符合另一部分条件的
+        let mut list: Vec<NaiveDate> = vec![];
+
+        // 如果指定了yearday但是无结果，则直接返回，如果有，push到list里
+        if !rrule.by_year_day.is_empty() {
+            // 先生成所有的year_day
+            let vec_by_year_day = rrule
+                .by_year_day
+                .iter()
+                .map(|day| Self::get_nth_day_of_year(curr_year, *day))
+                .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+                .map(|n| n.unwrap())
+                .collect::<Vec<NaiveDate>>();
+
+            if vec_by_year_day.is_empty() {
+                return vec![];
+            }
+            vec_by_year_day.into_iter().for_each(|n| list.push(n));
+        };
+
+        // 如果指定了bymonthday但是无结果，则直接返回
+        // 如果有值，则判断与list是做交集还是直接塞入
+        // 运算完后还是没有，则直接返回
+        if !rrule.by_month_day.is_empty() {
+            // 有则降级为月，但是与by_month可以直接取交集，性能可以接受，所以直接获取所有
+            let vec_by_month_day = rrule
+                .by_month_day
+                .iter()
+                .map(|month_day| {
+                    (1..=12)
+                        .filter(|month_day| {
+                            if rrule.by_month.is_empty() {
+                                true
+                            } else {
+                                rrule.by_month.contains(month_day)
+                            }
+                        })
+                        .map(|i| Self::get_nth_day_of_month(curr_year, i as u32, *month_day))
+                        .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+                        .map(|n| n.unwrap())
+                        .collect::<Vec<NaiveDate>>()
+                })
+                .flatten()
+                .collect::<Vec<NaiveDate>>();
+
+            if vec_by_month_day.is_empty() {
+                return vec![];
+            }
+            if !list.is_empty() {
+                list = list
+                    .into_iter()
+                    .filter(|n| vec_by_month_day.contains(&n))
+                    .collect::<Vec<NaiveDate>>();
             } else {
-                &naive_end_time
-            };
+                vec_by_month_day.into_iter().for_each(|n| list.push(n));
+            }
+            if list.is_empty() {
+                return vec![];
+            }
+        };
+
+        // - by_month和by_month_day结合会强制限定为某月某日，(同时无需在此时考虑by_day，因为后面还会按照by_day过滤)，
+        //   但是该逻辑已经在上面处理过了，所以只需要考虑by_month_day不存在的场景
+        // - 如果此时存在by_day，则会强制为该月所有周或指定周，类似于按月循环，可直接调用按月获取指定by_day的逻辑
+        if !rrule.by_month.is_empty() {
+            // let vec_by_month = match rrule.by_day.is_empty() {
+            //     true => rrule
+            //         .by_month
+            //         .iter()
+            //         .map(|month| {
+            //             NaiveDate::from_ymd_opt(curr_year, *month as u32, point_time.day)
+            //         })
+            //         .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+            //         .map(|n| n.unwrap())
+            //         .collect::<Vec<NaiveDate>>(),
+            //     false => rrule
+            //         .by_month
+            //         .iter()
+            //         .map(|month| {
+            //             return rrule
+            //                 .by_day
+            //                 .iter()
+            //                 .map(|weekday| {
+            //                     Self::get_weekdays_by_nweekday_of_month(
+            //                         curr_year,
+            //                         *month as u32,
+            //                         weekday,
+            //                     )
+            //                 })
+            //                 .flatten()
+            //                 .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
+            //                 .collect::<Vec<NaiveDate>>();
+            //         })
+            //         .flatten()
+            //         .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
+            //         .collect::<Vec<NaiveDate>>(),
+            // };
+
+            // if !list.is_empty() {
+            //     list = list
+            //         .into_iter()
+            //         .filter(|n| vec_by_month.contains(&n))
+            //         .collect::<Vec<NaiveDate>>();
+            // } else {
+            //     vec_by_month.into_iter().for_each(|n| list.push(n));
+            // }
+            // if list.is_empty() {
+            //     return vec![];
+            // }
+
+            if list.len() != 0 {
+                list = list
+                    .into_iter()
+                    .filter(|n| rrule.by_month.contains(&(n.month() as u8)))
+                    .collect::<Vec<NaiveDate>>();
+                if list.is_empty() {
+                    return vec![];
+                }
+            }
+        }
 
-            // 只有BYMONTH的时候不会降级，还是执行按年重复，符合条件可以提前退出
-            if rrule.by_year_day.is_empty()
-                && rrule.by_month_day.is_empty()
-                && rrule.by_week_no.is_empty()
-                && rrule.by_day.is_empty()
-            {
-                let bindings = vec![point_time.month as u8];
-                return (if rrule.by_month.is_empty() {
-                    &bindings
-                } else {
-                    &rrule.by_month
-                })
+        // week_no只能跟by_day交集
+        if !rrule.by_week_no.is_empty() {
+            let vec_by_week_no = rrule
+                .by_week_no
                 .iter()
-                .map(|month| {
-                    NaiveDate::from_ymd_opt(curr_year, *month as u32, naive_dt_start.day())
+                .map(|week_no| {
+                    return Self::get_all_weekday()
+                        .into_iter()
+                        .filter(|weekday| {
+                            if rrule.by_day.is_empty() {
+                                true
+                            } else {
+                                rrule.by_day.contains(&NWeekday::Every(*weekday))
+                            }
+                        })
+                        .map(|weekday| {
+                            // NaiveDate::from_isoywd_opt(curr_year, *week_no as u32, weekday)
+                            if let Some(start) =
+                                Self::get_nth_week_by_week_no(curr_year, *week_no)
+                            {
+                                return Some(Self::get_next_weekday_by_time(&start, &weekday));
+                            }
+                            None
+                        })
+                        .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+                        .map(|n| n.unwrap())
+                        .collect::<Vec<NaiveDate>>();
                 })
-                .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
-                .map(|n| n.unwrap())
+                .flatten()
                 .collect::<Vec<NaiveDate>>();
-            }
 
-            // 先缓存符合一定条件的，再过滤不符合另一部分条件的
-            let mut list: Vec<NaiveDate> = vec![];
-
-            // 如果指定了yearday但是无结果，则直接返回，如果有，push到list里
-            if !rrule.by_year_day.is_empty() {
-                // 先生成所有的year_day
-                let vec_by_year_day = rrule
-                    .by_year_day
-                    .iter()
-                    .map(|day| Self::get_nth_day_of_year(curr_year, *day))
-                    .filter(|n| Self::is_option_date_in_range(&naive_dt_start, &naive_end_time, n))
-                    .map(|n| n.unwrap())
+            if !list.is_empty() {
+                list = list
+                    .into_iter()
+                    .filter(|n| vec_by_week_no.contains(&n))
                     .collect::<Vec<NaiveDate>>();
+            } else {
+                vec_by_week_no.into_iter().for_each(|n| list.push(n));
+            }
+            if list.is_empty() {
+                return vec![];
+            }
+        };
 
-                if vec_by_year_day.is_empty() {
-                    return vec![];
-                }
-                vec_by_year_day.into_iter().for_each(|n| list.push(n));
-            };
+        if !rrule.by_day.is_empty() {
+            let by_day_every = rrule
+                .by_day
+                .iter()
+                .filter(|n| n.is_every())
+                .map(|n| n.get_weekday().clone())
+                .collect::<Vec<Weekday>>();
+            let by_day_nth = rrule
+                .by_day
+                .iter()
+                .filter(|n| !n.is_every())
+                .map(|n| n.get_weekday().clone())
+                .collect::<Vec<Weekday>>();
 
-            // 如果指定了bymonthday但是无结果，则直接返回
-            // 如果有值，则判断与list是做交集还是直接塞入
-            // 运算完后还是没有，则直接返回
-            if !rrule.by_month_day.is_empty() {
-                // 有则降级为月，但是与by_month可以直接取交集，性能可以接受，所以直接获取所有
-                let vec_by_month_day = rrule
-                    .by_month_day
-                    .iter()
-                    .map(|month_day| {
-                        (1..12)
-                            .filter(|month_day| {
-                                if rrule.by_month.is_empty() {
-                                    true
-                                } else {
-                                    rrule.by_month.contains(month_day)
-                                }
-                            })
-                            .map(|i| Self::get_nth_day_of_month(curr_year, i as u32, *month_day))
-                            .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
-                            .map(|n| n.unwrap())
-                            .collect::<Vec<NaiveDate>>()
-                    })
-                    .flatten()
-                    .collect::<Vec<NaiveDate>>();
+            // 不支持混排，不知道为啥
+            if !by_day_nth.is_empty() && !by_day_every.is_empty() {
+                return vec![];
+            }
 
-                if vec_by_month_day.is_empty() {
-                    return vec![];
-                }
-                if !list.is_empty() {
-                    list = list
-                        .into_iter()
-                        .filter(|n| vec_by_month_day.contains(&n))
-                        .collect::<Vec<NaiveDate>>();
-                } else {
-                    vec_by_month_day.into_iter().for_each(|n| list.push(n));
-                }
+            // 如果此时有值，先过滤一次every的
+            if !list.is_empty() && !by_day_every.is_empty() {
+                list = list
+                    .into_iter()
+                    .filter(|n| by_day_every.contains(&n.weekday()))
+                    .collect::<Vec<NaiveDate>>();
                 if list.is_empty() {
                     return vec![];
                 }
-            };
-
-            // - by_month和by_month_day结合会强制限定为某月某日，(同时无需在此时考虑by_day，因为后面还会按照by_day过滤)，
-            //   但是该逻辑已经在上面处理过了，所以只需要考虑by_month_day不存在的场景
-            // - 如果此时存在by_day，则会强制为该月所有周或指定周，类似于按月循环，可直接调用按月获取指定by_day的逻辑
-            if !rrule.by_month.is_empty() {
-                // let vec_by_month = match rrule.by_day.is_empty() {
-                //     true => rrule
-                //         .by_month
-                //         .iter()
-                //         .map(|month| {
-                //             NaiveDate::from_ymd_opt(curr_year, *month as u32, point_time.day)
-                //         })
-                //         .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
-                //         .map(|n| n.unwrap())
-                //         .collect::<Vec<NaiveDate>>(),
-                //     false => rrule
-                //         .by_month
-                //         .iter()
-                //         .map(|month| {
-                //             return rrule
-                //                 .by_day
-                //                 .iter()
-                //                 .map(|weekday| {
-                //                     Self::get_weekdays_by_nweekday_of_month(
-                //                         curr_year,
-                //                         *month as u32,
-                //                         weekday,
-                //                     )
-                //                 })
-                //                 .flatten()
-                //                 .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
-                //                 .collect::<Vec<NaiveDate>>();
-                //         })
-                //         .flatten()
-                //         .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
-                //         .collect::<Vec<NaiveDate>>(),
-                // };
-
-                // if !list.is_empty() {
-                //     list = list
-                //         .into_iter()
-                //         .filter(|n| vec_by_month.contains(&n))
-                //         .collect::<Vec<NaiveDate>>();
-                // } else {
-                //     vec_by_month.into_iter().for_each(|n| list.push(n));
-                // }
-                // if list.is_empty() {
-                //     return vec![];
-                // }
-
-                if list.len() != 0 {
-                    list = list
-                        .into_iter()
-                        .filter(|n| rrule.by_month.contains(&(n.month() as u8)))
-                        .collect::<Vec<NaiveDate>>();
-                    if list.is_empty() {
-                        return vec![];
-                    }
-                }
             }
 
-            // week_no只能跟by_day交集
-            if !rrule.by_week_no.is_empty() {
-                let vec_by_week_no = rrule
-                    .by_week_no
-                    .iter()
-                    .map(|week_no| {
-                        return Self::get_all_weekday()
-                            .into_iter()
-                            .filter(|weekday| {
-                                if rrule.by_day.is_empty() {
-                                    true
-                                } else {
-                                    rrule.by_day.contains(&NWeekday::Every(*weekday))
-                                }
+            // 只处理by_day为every的场景,不考虑nth
+            let vec_by_day_every = rrule
+                .by_day
+                .iter()
+                .filter(|n| n.is_every())
+                .map(|n_weekday| {
+                    if rrule.by_month.is_empty() {
+                        // 年末几天可能属于下一年的第1周，一起取出后再按年份过滤
+                        return (1..=53)
+                            .map(|n| (curr_year, n))
+                            .chain([(curr_year + 1, 1)])
+                            .map(|(year, n)| {
+                                NaiveDate::from_isoywd_opt(year, n, *n_weekday.get_weekday())
                             })
-                            .map(|weekday| {
-                                // NaiveDate::from_isoywd_opt(curr_year, *week_no as u32, weekday)
-                                if let Some(start) =
-                                    Self::get_nth_week_by_week_no(curr_year, *week_no)
-                                {
-                                    return Some(Self::get_next_weekday_by_time(&start, &weekday));
-                                }
-                                None
+                            .filter(|n| {
+                                Self::is_option_date_in_range(valid_start, valid_end, n)
                             })
-                            .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
                             .map(|n| n.unwrap())
                             .collect::<Vec<NaiveDate>>();
-                    })
-                    .flatten()
-                    .collect::<Vec<NaiveDate>>();
-
-                if !list.is_empty() {
-                    list = list
-                        .into_iter()
-                        .filter(|n| vec_by_week_no.contains(&n))
+                    }
+                    // 如果指定了月份，则找当月的指定日期
+                    return rrule
+                        .by_month
+                        .iter()
+                        .map(|month| {
+                            return Self::get_all_weekday_of_month(
+                                curr_year,
+                                *month as u32,
+                                n_weekday.get_weekday(),
+                            )
+                            .into_iter()
+                            .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
+                            .collect::<Vec<NaiveDate>>();
+                        })
+                        .flatten()
                         .collect::<Vec<NaiveDate>>();
-                } else {
-                    vec_by_week_no.into_iter().for_each(|n| list.push(n));
-                }
-                if list.is_empty() {
-                    return vec![];
-                }
-            };
-
-            if !rrule.by_day.is_empty() {
-                let by_day_every = rrule
-                    .by_day
-                    .iter()
-                    .filter(|n| n.is_every())
-                    .map(|n| n.get_weekday().clone())
-                    .collect::<Vec<Weekday>>();
-                let by_day_nth = rrule
-                    .by_day
-                    .iter()
-                    .filter(|n| !n.is_every())
-                    .map(|n| n.get_weekday().clone())
-                    .collect::<Vec<Weekday>>();
-
-                // 不支持混排，不知道为啥
-                if !by_day_nth.is_empty() && !by_day_every.is_empty() {
-                    return vec![];
-                }
+                })
+                .flatten()
+                .collect::<Vec<NaiveDate>>();
 
-                // 如果此时有值，先过滤一次every的
-                if !list.is_empty() && !by_day_every.is_empty() {
-                    list = list
-                        .into_iter()
-                        .filter(|n| by_day_every.contains(&n.weekday()))
-                        .collect::<Vec<NaiveDate>>();
-                    if list.is_empty() {
+            // 只处理by_day为nth的场景,不考虑Every
+            let vec_by_day = rrule
+                .by_day
+                .iter()
+                .filter(|n| !n.is_every())
+                .map(|n_weekday| {
+                    if rrule.by_month.is_empty() {
+                        if let Some(time) =
+                            Self::get_weekday_by_nweekday_of_year(curr_year, n_weekday)
+                        {
+                            return vec![time];
+                        }
                         return vec![];
                     }
-                }
-
-                // 只处理by_day为every的场景,不考虑nth
-                let vec_by_day_every = rrule
-                    .by_day
-                    .iter()
-                    .filter(|n| n.is_every())
-                    .map(|n_weekday| {
-                        if rrule.by_month.is_empty() {
-                            return (1..53)
-                                .map(|n| {
-                                    NaiveDate::from_isoywd_opt(
-                                        curr_year,
-                                        n,
-                                        *n_weekday.get_weekday(),
-                                    )
-                                })
-                                .filter(|n| {
-                                    Self::is_option_date_in_range(valid_start, valid_end, n)
-                                })
-                                .map(|n| n.unwrap())
-                                .collect::<Vec<NaiveDate>>();
-                        }
-                        // 如果指定了月份，则找当月的指定日期
-                        return rrule
-                            .by_month
-                            .iter()
-                            .map(|month| {
-                                return Self::get_all_weekday_of_month(
-                                    curr_year,
-                                    *month as u32,
-                                    n_weekday.get_weekday(),
-                                )
-                                .into_iter()
-                                .filter(|n| Self::is_date_in_range(valid_start, valid_end, n))
-                                .collect::<Vec<NaiveDate>>();
-                            })
-                            .flatten()
-                            .collect::<Vec<NaiveDate>>();
-                    })
-                    .flatten()
-                    .collect::<Vec<NaiveDate>>();
+                    // 如果指定了月份，则找当月的指定日期
+                    return rrule
+                        .by_month
+                        .iter()
+                        .map(|month| {
+                            return Self::get_nth_weekday_of_month(
+                                curr_year,
+                                *month as u32,
+                                n_weekday.get_weekday(),
+                                n_weekday.get_nth(),
+                            );
+                        })
+                        .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
+                        .map(|n| n.unwrap())
+                        .collect::<Vec<NaiveDate>>();
+                })
+                .flatten()
+                .collect::<Vec<NaiveDate>>();
 
-                // 只处理by_day为nth的场景,不考虑Every
-                let vec_by_day = rrule
-                    .by_day
-                    .iter()
-                    .filter(|n| !n.is_every())
-                    .map(|n_weekday| {
-                        if rrule.by_month.is_empty() {
-                            if let Some(time) =
-                                Self::get_weekday_by_nweekday_of_year(curr_year, n_weekday)
-                            {
-                                return vec![time];
-                            }
-                            return vec![];
-                        }
-                        // 如果指定了月份，则找当月的指定日期
-                        return rrule
-                            .by_month
-                            .iter()
-                            .map(|month| {
-                                return Self::get_nth_weekday_of_month(
-                                    curr_year,
-                                    *month as u32,
-                                    n_weekday.get_weekday(),
-                                    n_weekday.get_nth(),
-                                );
-                            })
-                            .filter(|n| Self::is_option_date_in_range(valid_start, valid_end, n))
-                            .map(|n| n.unwrap())
-                            .collect::<Vec<NaiveDate>>();
-                    })
-                    .flatten()
+            let all_by_day = [vec_by_day, vec_by_day_every].concat();
+            if !list.is_empty() {
+                list = list
+                    .into_iter()
+                    .filter(|n| all_by_day.contains(&n))
                     .collect::<Vec<NaiveDate>>();
-
-                let all_by_day = [vec_by_day, vec_by_day_every].concat();
-                if !list.is_empty() {
-                    list = list
-                        .into_iter()
-                        .filter(|n| all_by_day.contains(&n))
-                        .collect::<Vec<NaiveDate>>();
-                } else {
-                    all_by_day.into_iter().for_each(|n| list.push(n));
-                }
-                if list.is_empty() {
-                    return vec![];
-                }
+            } else {
+                all_by_day.into_iter().for_each(|n| list.push(n));
             }
+            if list.is_empty() {
+                return vec![];
+            }
+        }
 
-            // 到这里肯定有值了
-
-            list.sort();
-            // todo by_set_pos
+        // 到这里肯定有值了
 
-            return list;
-        };
+        list.sort();
 
-        let mut curr_year = point_time.year;
-        let end_year = naive_end_time.year();
-        let mut result: Vec<PointTime> = vec![];
-
-        while result.len() < max && curr_year <= end_year {
-            let rs = generate_by_year(curr_year);
-            rs.into_iter().for_each(|n| {
-                result.push(PointTime {
-                    year: n.year(),
-                    month: n.month(),
-                    day: n.day(),
-                    hour: point_time.hour,
-                    min: point_time.min,
-                    sec: point_time.sec,
-                })
-            });
-            curr_year = curr_year + interval as i32;
-        }
-        let len = if result.len() < max {
-            result.len()
-        } else {
-            max
-        };
-        result = result[0..len].to_vec();
-        Ok(result)
+        return list;
 
         // ByDay(Vec<NWeekday>) 过滤/获取, 有则降级为按周循环
         // ByMonthDay(Vec<i16>) 获取，已处理, 有则降级为每月几号
@@ -948,7 +1024,7 @@ impl RRuleSet {
             return NaiveDate::from_ymd_opt(year, month, ordinal as u32);
         }
         if ordinal < 0 {
-            let last = Self::get_last_day_of_month(year, month);
+            let last = Self::get_last_day_of_month(year, month)?;
             let last_day = last.day();
             if last_day as i16 + ordinal + 1 > 0 {
                 let curr = last.with_day((last_day as i16 + ordinal + 1) as u32);
@@ -957,15 +1033,15 @@ impl RRuleSet {
         }
         return None;
     }
-    /// 获取该月最后一天
-    fn get_last_day_of_month(year: i32, month: u32) -> NaiveDate {
+    /// 获取该月最后一天，月份不存在时返回None
+    fn get_last_day_of_month(year: i32, month: u32) -> Option<NaiveDate> {
         let first_day_of_next_month = if month == 12 {
             NaiveDate::from_ymd_opt(year + 1, 1, 1)
         } else {
             NaiveDate::from_ymd_opt(year, month + 1, 1)
         };
 
-        first_day_of_next_month.unwrap().pred_opt().unwrap()
+        first_day_of_next_month?.pred_opt()
     }
     /// 获取该年最后一天
     fn get_last_day_of_year(year: i32) -> NaiveDate {
@@ -1065,7 +1141,7 @@ impl RRuleSet {
             .collect::<Vec<NaiveDate>>()
     }
 
-    /// 获取指定月份下第n个周的指定星期几
+    /// 获取指定月份下第n个周的指定星期几，月份不存在时返回None
     fn get_nth_weekday_of_month(
         year: i32,
         month: u32,
@@ -1076,17 +1152,17 @@ impl RRuleSet {
             return NaiveDate::from_weekday_of_month_opt(year, month, *weekday, n as u8);
         }
         // 下面处理 n < 0 的场景
-        let first_day_of_month = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
-        let last_day_of_month = Self::get_last_day_of_month(year, month);
+        let first_day_of_month = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let last_day_of_month = Self::get_last_day_of_month(year, month)?;
         let mut date = last_day_of_month;
 
         // 找到最后一个weekday
         while date.weekday() != *weekday {
-            date = date.pred_opt().unwrap();
+            date = date.pred_opt()?;
         }
 
         let diff = n + 1;
-        date = date + Duration::weeks(diff as i64);
+        date = date.checked_add_signed(Duration::weeks(diff as i64))?;
         if date >= first_day_of_month {
             return Some(date);
         }
@@ -1145,13 +1221,133 @@ impl RRuleSet {
         return None;
     }
 
+    /// 获取某天中满足BYHOUR、BYMINUTE、BYSECOND的所有时间，升序排列
+    /// - 比freq周期更小的部分按BYXXX展开，未设置时沿用time中的值
+    /// - 不小于freq周期的部分只做过滤，如HOURLY下的BYHOUR
+    fn get_times_of_day(rrule: &RRule, time: &NaiveDateTime) -> Vec<NaiveDateTime> {
+        let expand = |by_list: &Vec<u8>, curr: u32, freq: Frequency| -> Vec<u32> {
+            if by_list.is_empty() {
+                return vec![curr];
+            }
+            if rrule.freq < freq {
+                return by_list.iter().map(|n| *n as u32).collect();
+            }
+            if by_list.contains(&(curr as u8)) {
+                vec![curr]
+            } else {
+                vec![]
+            }
+        };
+        let hours = expand(&rrule.by_hour, time.hour(), Frequency::Hourly);
+        let minutes = expand(&rrule.by_minute, time.minute(), Frequency::Minutely);
+        let seconds = expand(&rrule.by_second, time.second(), Frequency::Secondly);
+        let date = time.date();
+
+        let mut list = vec![];
+        for hour in hours.iter() {
+            for min in minutes.iter() {
+                for sec in seconds.iter() {
+                    if let Some(n) = date.and_hms_opt(*hour, *min, *sec) {
+                        list.push(n);
+                    }
+                }
+            }
+        }
+        list
+    }
+
+    /// 过滤一个周期内满足条件的时间
+    /// - 先按BYSETPOS从整个周期中选取，再只保留dt_start到end之间的时间
+    fn filter_period(
+        rrule: &RRule,
+        dt_start: &NaiveDateTime,
+        end: Option<&NaiveDateTime>,
+        mut times: Vec<NaiveDateTime>,
+    ) -> Vec<NaiveDateTime> {
+        times.sort();
+        times.dedup();
+        Self::get_by_set_pos(rrule, times)
+            .into_iter()
+            .filter(|n| n >= dt_start && end.is_none_or(|end| n <= end))
+            .collect()
+    }
+
+    /// 按BYSETPOS选取周期内的时间，支持正负数，未设置时原样返回
+    fn get_by_set_pos(rrule: &RRule, times: Vec<NaiveDateTime>) -> Vec<NaiveDateTime> {
+        if rrule.by_set_pos.is_empty() {
+            return times;
+        }
+        // 用i64计算，避免取反i16::MIN以及一天的秒数超出i16时溢出
+        let len = times.len() as i64;
+        let mut list = rrule
+            .by_set_pos
+            .iter()
+            .filter_map(|pos| match i64::from(*pos) {
+                pos if pos > 0 && pos <= len => Some(times[(pos - 1) as usize]),
+                pos if pos < 0 && -pos <= len => Some(times[(len + pos) as usize]),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        list.sort();
+        list.dedup();
+        list
+    }
+
+    /// 获取某天的最后一秒
+    fn get_end_of_day(date: &NaiveDate) -> NaiveDateTime {
+        date.and_hms_opt(23, 59, 59).unwrap()
+    }
+
+    /// 判断给定的日期是否满足BYDAY、BYMONTH、BYMONTHDAY、BYYEARDAY、BYWEEKNO的限制
+    /// 此处BYDAY只按星期几过滤，不识别正负数
+    fn is_date_matched(rrule: &RRule, date: &NaiveDate) -> bool {
+        if !rrule.by_day.is_empty()
+            && !rrule
+                .by_day
+                .iter()
+                .any(|n| n.get_weekday() == &date.weekday())
+        {
+            return false;
+        }
+        if !rrule.by_month.is_empty() && !rrule.by_month.contains(&(date.month() as u8)) {
+            return false;
+        }
+        if !rrule.by_month_day.is_empty()
+            && !rrule
+                .by_month_day
+                .iter()
+                .any(|day| Self::is_nth_day_of_month(date, *day))
+        {
+            return false;
+        }
+        if !rrule.by_year_day.is_empty()
+            && !rrule
+                .by_year_day
+                .iter()
+                .any(|day| Self::is_nth_day_of_year(date, *day))
+        {
+            return false;
+        }
+        if !rrule.by_week_no.is_empty()
+            && !rrule
+                .by_week_no
+                .iter()
+                .any(|week_no| Self::is_in_nth_weekno(date, *week_no))
+        {
+            return false;
+        }
+        true
+    }
+
     /// 判断给定的时间是否是指定的某个月中的一天
     fn is_nth_day_of_month(time: &NaiveDate, day: i16) -> bool {
         if day > 0 {
             return time.day() == day as u32;
         }
         if day < 0 {
-            let last_day_of_month = Self::get_last_day_of_month(time.year(), time.month());
+            let Some(last_day_of_month) = Self::get_last_day_of_month(time.year(), time.month()) else {
+                return false;
+            };
             let last_day = last_day_of_month.day() as i16;
             if day > -31 && (last_day + day + 1) == time.day() as i16 {
                 return true;
@@ -1211,8 +1407,64 @@ impl RRuleSet {
         return Self::is_date_in_range(start, end, &curr.unwrap());
     }
 
-    pub fn from_json(json_str: &str) -> RRuleSet {
-        let parsed: Value = serde_json::from_str(json_str).expect("Failed to parse JSON");
+    pub fn from_json(json_str: &str) -> Result<RRuleSet, RRuleError> {
+        let parsed: Value = serde_json::from_str(json_str)
+            .map_err(|err| RRuleError::invalid_value("JSON", &err.to_string()))?;
+        RRuleSet::from_str(&Self::json_to_str(&parsed)?)
+    }
+
+    /// 宽松模式解析json，字符串的值可以带 `RRULE:`、`UNTIL=` 等前缀，数字可以是字符串
+    /// 规则按`from_str_lenient`解析
+    pub fn from_json_lenient(json_str: &str) -> Result<(RRuleSet, Vec<String>), RRuleError> {
+        let mut parsed: Value = serde_json::from_str(json_str)
+            .map_err(|err| RRuleError::invalid_value("JSON", &err.to_string()))?;
+        const NUMBER_KEYS: [&str; 10] = [
+            "count", "interval", "byMonthDay", "byMonth", "byYearDay", "byWeekNo", "byHour",
+            "byMinute", "bySecond", "bySetPos",
+        ];
+        let mut warnings = vec![];
+        if let Some(map) = parsed.as_object_mut() {
+            for (key, value) in map.iter_mut() {
+                let values = match value {
+                    Value::Array(list) => list.iter_mut().collect::<Vec<_>>(),
+                    value => vec![value],
+                };
+                for value in values {
+                    let Some(str) = value.as_str() else {
+                        continue;
+                    };
+                    let stripped = Self::strip_json_prefix(str);
+                    if stripped != str {
+                        warnings.push(format!("prefix of {} is removed: {}", key, str));
+                    }
+                    *value = match stripped.parse::<i64>() {
+                        Ok(number) if NUMBER_KEYS.contains(&&key[..]) => Value::from(number),
+                        _ => Value::from(stripped),
+                    };
+                }
+            }
+        }
+        let (set, rrule_warnings) = RRuleSet::from_str_lenient(&Self::json_to_str(&parsed)?)?;
+        warnings.extend(rrule_warnings);
+        Ok((set, warnings))
+    }
+
+    /// 去掉json中字符串值的前缀，如 `RRULE:FREQ=DAILY` 转为 `DAILY`
+    fn strip_json_prefix(str: &str) -> &str {
+        let is_name = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic());
+        let mut value = str.trim();
+        for separator in [':', '='] {
+            if let Some((name, rest)) = value.split_once(separator) {
+                if is_name(name.trim()) {
+                    value = rest.trim();
+                }
+            }
+        }
+        value
+    }
+
+    /// 把json转为字符串，格式同from_str
+    fn json_to_str(parsed: &Value) -> Result<String, RRuleError> {
         let dt_start = parsed["dtStart"].as_str();
         let tz = parsed["tz"].as_str();
         let freq = parsed["freq"].as_str().unwrap_or("WEEKLY");
@@ -1225,6 +1477,12 @@ impl RRuleSet {
         let by_month = parsed["byMonth"].as_array();
         let by_year_day = parsed["byYearDay"].as_array();
         let by_week_no = parsed["byWeekNo"].as_array();
+        let by_hour = parsed["byHour"].as_array();
+        let by_minute = parsed["byMinute"].as_array();
+        let by_second = parsed["bySecond"].as_array();
+        let by_set_pos = parsed["bySetPos"].as_array();
+        let rdate = parsed["rDate"].as_array();
+        let exdate = parsed["exDate"].as_array();
 
         let mut rrule_str = String::from("");
 
@@ -1256,8 +1514,10 @@ impl RRuleSet {
                 by_day
                     .unwrap()
                     .iter()
-                    .map(|n| n.as_str().unwrap())
-                    .collect::<Vec<_>>()
+                    .map(|n| n
+                        .as_str()
+                        .ok_or_else(|| RRuleError::invalid_value("BYDAY", &n.to_string())))
+                    .collect::<Result<Vec<_>, _>>()?
                     .join(",")
             );
         }
@@ -1306,107 +1566,90 @@ impl RRuleSet {
             );
         }
 
-        RRuleSet::from_str(&rrule_str).unwrap()
-    }
-
-    pub fn to_string(&self) -> String {
-        let mut str = "".to_string();
-        if self.rrule.is_empty() {
-            return str;
-        }
-        let rrule = &self.rrule[0];
-        if let Some(time) = &self.start_point_time {
-            let start = if self.tz == Tz::UTC {
-                format!("DTSTART:{}\n", time.to_string())
-            } else {
-                format!(
-                    "DTSTART;TZID={}:{}\n",
-                    self.tz.to_string(),
-                    time.to_string()
-                )
-            };
-            str += &start;
+        for (key, list) in [
+            ("BYHOUR", by_hour),
+            ("BYMINUTE", by_minute),
+            ("BYSECOND", by_second),
+            ("BYSETPOS", by_set_pos),
+        ] {
+            if let Some(list) = list {
+                rrule_str += &format!(
+                    "{}={};",
+                    key,
+                    list.iter()
+                        .map(|n| n.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                );
+            }
         }
-        str += "RRULE:";
 
-        str += &format!("FREQ={};", rrule.freq.to_string());
-        if rrule.count != 0 {
-            str += &format!("COUNT={};", rrule.count.to_string())
-        }
-        if let Some(until) = &rrule.until {
-            str += &format!("UNTIL={};", until.to_string())
-        }
-        if rrule.interval != 1 {
-            str += &format!("INTERVAL={};", rrule.interval.to_string())
-        }
-        if !rrule.by_day.is_empty() {
-            str += &format!(
-                "BYDAY={};",
-                rrule
-                    .by_day
-                    .iter()
-                    .map(|n| n.to_string())
-                    .collect::<Vec<_>>()
-                    .join(",")
-            )
+        for (name, dates) in [("RDATE", rdate), ("EXDATE", exdate)] {
+            let list = dates
+                .map(|dates| {
+                    dates
+                        .iter()
+                        .filter_map(|n| n.as_str())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .unwrap_or_default();
+            if !list.is_empty() {
+                rrule_str += &match tz {
+                    Some(tz) => format!("\n{};TZID={}:{}", name, tz, list),
+                    None => format!("\n{}:{}", name, list),
+                };
+            }
         }
 
-        str += &format!("WKST={};", NWeekday::weekday_to_str(&rrule.week_start));
-        if !rrule.by_week_no.is_empty() {
-            str += &format!(
-                "BYWEEKNO={};",
-                rrule
-                    .by_week_no
-                    .iter()
-                    .map(|n| n.to_string())
-                    .collect::<Vec<_>>()
-                    .join(",")
-            );
-        }
-        if !rrule.by_month_day.is_empty() {
-            str += &format!(
-                "BYMONTHDAY={};",
-                rrule
-                    .by_month_day
-                    .iter()
-                    .map(|n| n.to_string())
-                    .collect::<Vec<_>>()
-                    .join(",")
-            );
+        Ok(rrule_str)
+    }
+
+    /// 输出tz下的时间列表，日期和时间分为两行，日期带VALUE=DATE，时间不是UTC时带TZID
+    pub(crate) fn date_list_lines(name: &str, times: &[&PointTime], tz: &Timezone) -> Vec<String> {
+        let (dates, times): (Vec<&PointTime>, Vec<&PointTime>) =
+            times.iter().partition(|n| n.form == TimeForm::Date);
+        let join = |list: Vec<&PointTime>| {
+            list.iter()
+                .map(|n| Self::form_in(tz, n).to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let mut lines = vec![];
+        if !dates.is_empty() {
+            lines.push(format!("{};VALUE=DATE:{}", name, join(dates)));
         }
-        if !rrule.by_month.is_empty() {
-            str += &format!(
-                "BYMONTH={};",
-                rrule
-                    .by_month
-                    .iter()
-                    .map(|n| n.to_string())
-                    .collect::<Vec<_>>()
-                    .join(",")
-            );
+        if !times.is_empty() {
+            lines.push(if *tz == Tz::UTC {
+                format!("{}:{}", name, join(times))
+            } else {
+                format!("{};TZID={}:{}", name, tz, join(times))
+            });
         }
-        if !rrule.by_year_day.is_empty() {
-            str += &format!(
-                "BYYEARDAY={};",
-                rrule
-                    .by_year_day
-                    .iter()
-                    .map(|n| n.to_string())
-                    .collect::<Vec<_>>()
-                    .join(",")
-            );
+        lines
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut lines: Vec<String> = vec![];
+        if let Some(time) = &self.start_point_time {
+            lines.extend(Self::date_list_lines("DTSTART", &[time], &self.tz));
         }
-        if let Some((i, char)) = str.char_indices().rev().next() {
-            str.remove(i);
+        self.rrule
+            .iter()
+            .for_each(|rrule| lines.push(format!("RRULE:{}", self.rrule_for_output(rrule))));
+        self.exrule
+            .iter()
+            .for_each(|exrule| lines.push(format!("EXRULE:{}", self.rrule_for_output(exrule))));
+        for (name, dates) in [("RDATE", &self.rdate), ("EXDATE", &self.exdate)] {
+            lines.extend(Self::date_list_lines(name, &dates.iter().collect::<Vec<_>>(), &self.tz));
         }
-        str
+        lines.extend(self.other_lines.iter().cloned());
+        lines.join("\n")
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::rrule::Frequency;
-
     use super::*;
 
     #[test]
@@ -1515,7 +1758,8 @@ mod test {
         )
         .unwrap();
 
-        let dates = set.expand_by_week().unwrap();
+        let dates = RRuleIter::new(&set.rrule[0], set.start_point_time.as_ref().unwrap())
+            .collect::<Vec<_>>();
         assert_eq!(dates.len(), 2);
         let first = dates.get(0).unwrap();
         assert_eq!(first, &"20231024T180000Z".parse().unwrap());
@@ -1526,8 +1770,8 @@ mod test {
     #[test]
     fn test_rruleset() {
         let mut rrule_set = RRuleSet::from_str("RRULE:FREQ=WEEKLY;COUNT=3").unwrap();
-        rrule_set.set_dt_start("20231001T180000");
-        rrule_set.tz("America/New_York");
+        rrule_set.set_dt_start("20231001T180000").unwrap();
+        rrule_set.tz("America/New_York").unwrap();
         let dates = rrule_set.all();
         assert_eq!(dates.len(), 3);
     }
@@ -1556,13 +1800,19 @@ mod test {
             "byMonth": [2, 3],
             "byWeekNo": [1,-1],
             "byYearDay": [1, 50],
+            "byHour": [9, 14],
+            "byMinute": [0, 30],
+            "bySecond": [0],
+            "bySetPos": [1, -1],
+            "rDate": ["20231110T120000Z"],
+            "exDate": ["20231105T120000Z", "20231106T120000Z"],
             "tz": "America/New_York"
         }
         "#;
-        let rrule_set = RRuleSet::from_json(json_str);
+        let rrule_set = RRuleSet::from_json(json_str).unwrap();
         assert_eq!(
             rrule_set.start_point_time.unwrap().to_string(),
-            "20231101T120000Z"
+            "20231101T120000"
         );
         assert_eq!(rrule_set.rrule[0].count, 3);
         assert_eq!(rrule_set.rrule[0].freq, Frequency::Daily);
@@ -1583,6 +1833,21 @@ mod test {
         assert_eq!(rrule_set.rrule[0].by_month, vec![2, 3]);
         assert_eq!(rrule_set.rrule[0].by_week_no, vec![1, -1]);
         assert_eq!(rrule_set.rrule[0].by_year_day, vec![1, 50]);
+        assert_eq!(rrule_set.rrule[0].by_hour, vec![9, 14]);
+        assert_eq!(rrule_set.rrule[0].by_minute, vec![0, 30]);
+        assert_eq!(rrule_set.rrule[0].by_second, vec![0]);
+        assert_eq!(rrule_set.rrule[0].by_set_pos, vec![1, -1]);
+        assert_eq!(
+            rrule_set.rdate,
+            vec!["20231110T120000".parse::<PointTime>().unwrap()]
+        );
+        assert_eq!(
+            rrule_set.exdate,
+            vec![
+                "20231105T120000".parse::<PointTime>().unwrap(),
+                "20231106T120000".parse::<PointTime>().unwrap()
+            ]
+        );
         assert_eq!(rrule_set.tz, Tz::America__New_York);
     }
 
@@ -1592,4 +1857,24 @@ mod test {
             RRuleSet::from_str("DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=30;WKST=MO;BYWEEKNO=3;UNTIL=20260112T091700;INTERVAL=3;BYDAY=SU,-1FR,2WE;BYMONTHDAY=1,2,3;BYMONTH=2,4;BYYEARDAY=1,-1").unwrap().to_string(), 
             "DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=30;UNTIL=20260112T091700Z;INTERVAL=3;BYDAY=SU,-1FR,2WE;WKST=MO;BYWEEKNO=3;BYMONTHDAY=1,2,3;BYMONTH=2,4;BYYEARDAY=1,-1");
     }
+
+    #[test]
+    fn test_to_string_with_time() {
+        assert_eq!(
+            RRuleSet::from_str("DTSTART:20231123T091800Z\nRRULE:FREQ=DAILY;BYSECOND=0;BYMINUTE=30,0;BYHOUR=14,9;COUNT=4;BYSETPOS=-1,2").unwrap().to_string(),
+            "DTSTART:20231123T091800Z\nRRULE:FREQ=DAILY;COUNT=4;WKST=SU;BYHOUR=9,14;BYMINUTE=0,30;BYSECOND=0;BYSETPOS=-1,2"
+        );
+    }
+
+    #[test]
+    fn test_get_by_set_pos() {
+        let times = (0..3)
+            .map(|n| NaiveDate::from_ymd_opt(2023, 10, 23 + n).unwrap().and_hms_opt(9, 0, 0).unwrap())
+            .collect::<Vec<_>>();
+        let mut rrule = RRule::from_str("FREQ=DAILY;BYHOUR=9;BYSETPOS=1,-1").unwrap();
+        assert_eq!(RRuleSet::get_by_set_pos(&rrule, times.clone()), vec![times[0], times[2]]);
+        // 超出范围的位置被忽略，取反i16::MIN不会溢出
+        rrule.by_set_pos = vec![i16::MIN, i16::MAX, 4, -4];
+        assert!(RRuleSet::get_by_set_pos(&rrule, times).is_empty());
+    }
 }
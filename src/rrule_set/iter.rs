@@ -0,0 +1,306 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::rc::Rc;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use super::RRuleSet;
+use crate::constant;
+use crate::point_time::{PointTime, TimeForm};
+use crate::rrule::RRule;
+use crate::timezone::Timezone;
+
+/// 同一个RRuleSetIter中的规则共享的展开次数，以及是否因为限制提前结束
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    iterations: Cell<usize>,
+    truncated: Cell<bool>,
+    /// 提前结束时，早于这个时间的结果是完整的
+    complete_before: Cell<Option<NaiveDateTime>>,
+}
+
+impl Budget {
+    /// 记录提前结束，早于complete_before的结果是完整的
+    fn truncate(&self, complete_before: NaiveDateTime) {
+        self.truncated.set(true);
+        let min = self.complete_before.get().map_or(complete_before, |n| n.min(complete_before));
+        self.complete_before.set(Some(min));
+    }
+
+    /// 时间是否晚于提前结束的位置，这时不能确定结果
+    fn is_incomplete_at(&self, time: &PointTime) -> bool {
+        let complete_before = self.complete_before.get();
+        complete_before.is_some_and(|n| time.to_naive_date_time().is_none_or(|time| time >= n))
+    }
+}
+
+/// 单条规则的惰性展开，按周期依次生成时间
+pub struct RRuleIter<'a> {
+    rrule: &'a RRule,
+    dt_start: NaiveDateTime,
+    /// 截止时间，None表示没有截止时间
+    end: Option<NaiveDateTime>,
+    /// 最多返回的个数，None表示不限制
+    max: Option<usize>,
+    count: usize,
+    /// 下一个要展开的周期，None表示已结束
+    period: Option<NaiveDateTime>,
+    /// 当前周期内还未返回的时间
+    buffer: VecDeque<NaiveDateTime>,
+    /// 展开的范围，超出后结束并记录为被截断
+    horizon: NaiveDateTime,
+    /// 最多展开的周期数，与budget中的其他规则共享
+    max_iterations: usize,
+    budget: Rc<Budget>,
+}
+
+impl<'a> RRuleIter<'a> {
+    /// - 设置了count时最多返回count个
+    /// - 设置了until时以until为截止时间
+    /// - 都没设置时不会结束，展开到MAX_YEAR为止，需要调用方限制范围或个数
+    /// - 最多展开MAX_ITERATIONS个周期
+    pub fn new(rrule: &'a RRule, dt_start: &PointTime) -> Self {
+        Self::new_in_timezone(rrule, dt_start, &Timezone::UTC)
+    }
+
+    /// 同new，dt_start是tz下的当地时间，UTC写法的until先转换为tz下的当地时间
+    pub fn new_in_timezone(rrule: &'a RRule, dt_start: &PointTime, tz: &Timezone) -> Self {
+        let dt_start = dt_start.to_naive_date_time();
+        let until = rrule.until.as_ref().map(|until| match until.form {
+            TimeForm::Utc => until.convert_timezone(&Timezone::UTC, tz),
+            _ => until.clone(),
+        });
+        // 按完整时间比较，日期写法的until包含当天
+        let end = until
+            .as_ref()
+            .and_then(|until| Some((until.to_naive_date_time()?, until.form)))
+            .map(|(end, form)| match form {
+                TimeForm::Date => RRuleSet::get_end_of_day(&end.date()),
+                _ => end,
+            });
+
+        RRuleIter {
+            rrule,
+            dt_start: dt_start.unwrap_or_default(),
+            end,
+            max: if rrule.count == 0 {
+                None
+            } else {
+                Some(rrule.count as usize)
+            },
+            count: 0,
+            period: dt_start,
+            buffer: VecDeque::new(),
+            horizon: get_default_horizon(),
+            max_iterations: constant::MAX_ITERATIONS,
+            budget: Rc::default(),
+        }
+    }
+
+    /// 设置展开的范围和最多展开的周期数，budget由同一个RRuleSetIter中的规则共享
+    pub(crate) fn with_limits(
+        mut self,
+        horizon: NaiveDateTime,
+        max_iterations: usize,
+        budget: Rc<Budget>,
+    ) -> Self {
+        self.horizon = horizon;
+        self.max_iterations = max_iterations;
+        self.budget = budget;
+        self
+    }
+
+    /// 是否因为horizon或max_iterations提前结束了展开
+    pub fn is_truncated(&self) -> bool {
+        self.budget.truncated.get()
+    }
+}
+
+/// 默认的展开范围，到MAX_YEAR的最后一秒
+fn get_default_horizon() -> NaiveDateTime {
+    RRuleSet::get_end_of_day(&NaiveDate::from_ymd_opt(constant::MAX_YEAR, 12, 31).unwrap())
+}
+
+impl<'a> Iterator for RRuleIter<'a> {
+    type Item = PointTime;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.max.is_some_and(|max| self.count >= max) {
+            return None;
+        }
+        while self.buffer.is_empty() {
+            let period = self.period?;
+            if self.end.is_some_and(|end| period > end) {
+                self.period = None;
+                return None;
+            }
+            // 规则还没有结束，但超出了展开的范围或次数
+            let iterations = self.budget.iterations.get();
+            if period > self.horizon || iterations >= self.max_iterations {
+                // 超出范围时，范围内的结果都已返回
+                let after_horizon = self.horizon + chrono::Duration::seconds(1);
+                self.budget.truncate(period.min(after_horizon));
+                self.period = None;
+                return None;
+            }
+            self.budget.iterations.set(iterations + 1);
+            let end = self.end.map_or(self.horizon, |end| end.min(self.horizon));
+            let times = RRuleSet::expand_period(self.rrule, &self.dt_start, &period);
+            self.buffer.extend(RRuleSet::filter_period(
+                self.rrule,
+                &self.dt_start,
+                Some(&end),
+                times,
+            ));
+            self.period = RRuleSet::get_next_period(self.rrule, &period);
+        }
+        self.count += 1;
+        self.buffer
+            .pop_front()
+            .map(|n| PointTime::from_naive_date_time(&n))
+    }
+}
+
+type Source<'a> = Peekable<Box<dyn Iterator<Item = PointTime> + 'a>>;
+
+/// RRuleSet的惰性展开
+/// - 合并所有RRULE和RDATE，按时间排序并去重
+/// - 去掉EXDATE和EXRULE中的时间
+/// - 只返回between范围内的时间
+/// - 夏令时切换时不存在或重复的时间按dst_policy处理
+pub struct RRuleSetIter<'a> {
+    set: &'a RRuleSet,
+    sources: Vec<Source<'a>>,
+    exrules: Vec<Peekable<RRuleIter<'a>>>,
+    last: Option<PointTime>,
+    /// RRULE共享的展开次数
+    budget: Rc<Budget>,
+    /// EXRULE共享的展开次数，EXRULE提前结束后，之后的时间无法判断是否被排除
+    exrule_budget: Rc<Budget>,
+    /// between的范围，已转换为tz下的时刻
+    between_start: Option<DateTime<Timezone>>,
+    between_end: Option<DateTime<Timezone>>,
+    /// 上一次返回的时间，不存在的时间后移后可能与之后的时间重复
+    last_returned: Option<DateTime<Timezone>>,
+}
+
+impl<'a> RRuleSetIter<'a> {
+    pub fn new(set: &'a RRuleSet) -> Self {
+        let mut sources: Vec<Source<'a>> = vec![];
+        let mut exrules = vec![];
+        let budget = Rc::new(Budget::default());
+        let exrule_budget = Rc::new(Budget::default());
+        let horizon = set
+            .options
+            .horizon
+            .as_ref()
+            .map_or_else(get_default_horizon, |n| set.to_date_time(n).naive_local());
+        let max_iterations = set.options.max_iterations;
+        // 每条RRULE都基于同一个dt_start展开，没有dt_start时只返回RDATE
+        if let Some(dt_start) = &set.start_point_time {
+            for rrule in set.rrule.iter() {
+                let iter: Box<dyn Iterator<Item = PointTime> + 'a> = Box::new(
+                    RRuleIter::new_in_timezone(rrule, dt_start, &set.tz).with_limits(
+                        horizon,
+                        max_iterations,
+                        budget.clone(),
+                    ),
+                );
+                sources.push(iter.peekable());
+            }
+            for exrule in set.exrule.iter() {
+                let iter = RRuleIter::new_in_timezone(exrule, dt_start, &set.tz).with_limits(
+                    horizon,
+                    max_iterations,
+                    exrule_budget.clone(),
+                );
+                exrules.push(iter.peekable());
+            }
+        }
+        let mut rdate = set.rdate.clone();
+        rdate.sort();
+        let iter: Box<dyn Iterator<Item = PointTime> + 'a> = Box::new(rdate.into_iter());
+        sources.push(iter.peekable());
+
+        RRuleSetIter {
+            set,
+            sources,
+            exrules,
+            last: None,
+            budget,
+            exrule_budget,
+            between_start: set.between_start.as_ref().map(|n| set.to_date_time(n)),
+            between_end: set.between_end.as_ref().map(|n| set.to_date_time(n)),
+            last_returned: None,
+        }
+    }
+
+    /// 是否因为`ExpandOptions`的horizon或max_iterations丢弃了一部分时间
+    pub fn is_truncated(&self) -> bool {
+        self.budget.truncated.get()
+    }
+
+    /// 取出所有来源中最早的时间
+    fn next_candidate(&mut self) -> Option<PointTime> {
+        let mut min_index: Option<usize> = None;
+        let mut min_time: Option<&PointTime> = None;
+        for (index, source) in self.sources.iter_mut().enumerate() {
+            if let Some(time) = source.peek() {
+                if min_time.is_none_or(|min| time < min) {
+                    min_time = Some(time);
+                    min_index = Some(index);
+                }
+            }
+        }
+        self.sources[min_index?].next()
+    }
+
+    /// 判断时间是否被EXRULE排除，EXRULE也是升序的，跳过比它早的时间即可
+    fn is_excluded_by_exrule(&mut self, time: &PointTime) -> bool {
+        let mut excluded = false;
+        for exrule in self.exrules.iter_mut() {
+            while exrule.next_if(|n| n < time).is_some() {}
+            if exrule.peek() == Some(time) {
+                excluded = true;
+            }
+        }
+        excluded
+    }
+}
+
+impl<'a> Iterator for RRuleSetIter<'a> {
+    type Item = DateTime<Timezone>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let time = self.next_candidate()?;
+            if self.last.as_ref() == Some(&time) {
+                continue;
+            }
+            self.last = Some(time.clone());
+
+            if self.set.exdate.contains(&time) || self.is_excluded_by_exrule(&time) {
+                continue;
+            }
+            if self.exrule_budget.is_incomplete_at(&time) {
+                self.budget.truncated.set(true);
+                return None;
+            }
+            // 按dst_policy跳过的时间，以及后移后不晚于上一次的时间都不返回
+            let time = match time.with_timezone_policy(&self.set.tz, &self.set.dst_policy) {
+                Some(time) if self.last_returned.as_ref().is_none_or(|last| &time > last) => time,
+                _ => continue,
+            };
+            // 返回的时间是递增的，超出between_end后直接结束
+            if self.between_end.as_ref().is_some_and(|end| &time > end) {
+                return None;
+            }
+            if self.between_start.as_ref().is_some_and(|start| &time < start) {
+                continue;
+            }
+            self.last_returned = Some(time.clone());
+            return Some(time);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Datelike, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+
+use crate::error::RRuleError;
+use crate::point_time::PointTime;
+use crate::rrule::{get_tz_from_str, Frequency, RRule};
+use crate::rrule_set::RRuleIter;
+
+/// The timezone of a [`crate::rrule_set::RRuleSet`], either an IANA timezone of chrono-tz
+/// or a [`CustomTimezone`] built from a `VTIMEZONE`
+#[derive(Clone, Debug)]
+pub enum Timezone {
+    Tz(Tz),
+    Custom(Arc<CustomTimezone>),
+}
+
+impl Timezone {
+    pub const UTC: Timezone = Timezone::Tz(Tz::UTC);
+
+    /// The `TZID` of the timezone
+    pub fn name(&self) -> &str {
+        match self {
+            Timezone::Tz(tz) => tz.name(),
+            Timezone::Custom(tz) => &tz.tzid,
+        }
+    }
+}
+
+impl From<Tz> for Timezone {
+    fn from(tz: Tz) -> Self {
+        Timezone::Tz(tz)
+    }
+}
+
+impl From<CustomTimezone> for Timezone {
+    fn from(tz: CustomTimezone) -> Self {
+        Timezone::Custom(Arc::new(tz))
+    }
+}
+
+impl PartialEq for Timezone {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Timezone::Tz(a), Timezone::Tz(b)) => a == b,
+            (Timezone::Custom(a), Timezone::Custom(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<Tz> for Timezone {
+    fn eq(&self, other: &Tz) -> bool {
+        matches!(self, Timezone::Tz(tz) if tz == other)
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// 按名称查找时区，先查找IANA时区，找不到时使用名称相同的自定义时区
+pub fn get_timezone(name: &str, timezones: &[Timezone]) -> Result<Timezone, RRuleError> {
+    match get_tz_from_str(name) {
+        Ok(tz) => Ok(Timezone::Tz(tz)),
+        Err(err) => timezones
+            .iter()
+            .find(|n| n.name() == name)
+            .cloned()
+            .ok_or(err),
+    }
+}
+
+/// The offset of a [`Timezone`] at some time
+#[derive(Clone, Debug)]
+pub enum TimezoneOffset {
+    Tz(<Tz as TimeZone>::Offset),
+    Custom(Arc<CustomTimezone>, FixedOffset),
+}
+
+impl Offset for TimezoneOffset {
+    fn fix(&self) -> FixedOffset {
+        match self {
+            TimezoneOffset::Tz(offset) => offset.fix(),
+            TimezoneOffset::Custom(_, offset) => *offset,
+        }
+    }
+}
+
+impl fmt::Display for TimezoneOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimezoneOffset::Tz(offset) => write!(f, "{}", offset),
+            TimezoneOffset::Custom(_, offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl TimeZone for Timezone {
+    type Offset = TimezoneOffset;
+
+    fn from_offset(offset: &Self::Offset) -> Self {
+        match offset {
+            TimezoneOffset::Tz(offset) => Timezone::Tz(Tz::from_offset(offset)),
+            TimezoneOffset::Custom(tz, _) => Timezone::Custom(tz.clone()),
+        }
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<Self::Offset> {
+        match local.and_hms_opt(0, 0, 0) {
+            Some(time) => self.offset_from_local_datetime(&time),
+            None => LocalResult::None,
+        }
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<Self::Offset> {
+        match self {
+            Timezone::Tz(tz) => tz.offset_from_local_datetime(local).map(TimezoneOffset::Tz),
+            Timezone::Custom(tz) => tz
+                .offset_from_local(local)
+                .map(|n| TimezoneOffset::Custom(tz.clone(), n)),
+        }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> Self::Offset {
+        self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> Self::Offset {
+        match self {
+            Timezone::Tz(tz) => TimezoneOffset::Tz(tz.offset_from_utc_datetime(utc)),
+            Timezone::Custom(tz) => TimezoneOffset::Custom(tz.clone(), tz.offset_from_utc(utc)),
+        }
+    }
+}
+
+/// 一次切换，为切换时刻的UTC时间和切换后的偏移
+pub(crate) type OffsetChange = (NaiveDateTime, FixedOffset);
+
+/// A timezone built from a `VTIMEZONE`, see [`crate::ical::parse_vtimezone`]
+#[derive(Debug)]
+pub struct CustomTimezone {
+    pub tzid: String,
+    /// 第一次切换前使用的偏移
+    initial_offset: FixedOffset,
+    /// 按时间排序的切换
+    transitions: Vec<OffsetChange>,
+    /// 没有结束的规则，按年展开
+    recurring: Vec<RecurringTransition>,
+    /// 所有可能的偏移，从大到小
+    offsets: Vec<FixedOffset>,
+    /// recurring按年展开的结果，key为当地时间的年份
+    cache: Mutex<HashMap<i32, Vec<OffsetChange>>>,
+    /// 原始的VTIMEZONE中的行，导出时原样输出
+    pub(crate) lines: Vec<String>,
+}
+
+impl PartialEq for CustomTimezone {
+    fn eq(&self, other: &Self) -> bool {
+        self.tzid == other.tzid
+            && self.initial_offset == other.initial_offset
+            && self.transitions == other.transitions
+            && self.recurring == other.recurring
+            && self.lines == other.lines
+    }
+}
+
+/// STANDARD或DAYLIGHT中没有COUNT和UNTIL的RRULE，不展开到MAX_YEAR，用到哪一年再展开哪一年
+#[derive(PartialEq, Debug)]
+pub(crate) struct RecurringTransition {
+    pub(crate) rrule: RRule,
+    /// DTSTART，切换前的当地时间
+    pub(crate) start: NaiveDateTime,
+    pub(crate) offset_from: FixedOffset,
+    pub(crate) offset_to: FixedOffset,
+}
+
+impl RecurringTransition {
+    /// 当地时间在year年的切换
+    fn transitions_in_year(&self, year: i32) -> Vec<OffsetChange> {
+        if year < self.start.year() {
+            return vec![];
+        }
+        let start = PointTime::from_naive_date_time(&self.start_before(year).unwrap_or(self.start));
+        RRuleIter::new(&self.rrule, &start)
+            .take_while(|n| n.year <= year)
+            .filter(|n| n.year == year)
+            .filter_map(|n| n.to_naive_date_time())
+            .map(|local| {
+                let utc = local - Duration::seconds(self.offset_from.local_minus_utc() as i64);
+                (utc, self.offset_to)
+            })
+            .collect()
+    }
+
+    /// 早于year年初、与DTSTART相隔整数个周期的开始时间，从这里展开与从DTSTART展开的结果相同
+    /// 多退一个周期，WEEKLY的第一周从开始时间算起，不能落在year中
+    fn start_before(&self, year: i32) -> Option<NaiveDateTime> {
+        let start = self.start;
+        let interval = self.rrule.interval.max(1) as i64;
+        let target = NaiveDate::from_ymd_opt(year, 1, 1)?.and_hms_opt(0, 0, 0)?;
+        let unit = match self.rrule.freq {
+            Frequency::Yearly | Frequency::Monthly => {
+                let unit = interval * if self.rrule.freq == Frequency::Yearly { 12 } else { 1 };
+                let months = (year - start.year()) as i64 * 12 - start.month0() as i64;
+                // 月末的日期在有些月份不存在，继续往前找
+                let mut periods = months.div_euclid(unit) - 1;
+                while periods > 0 {
+                    let month = start.month0() as i64 + periods * unit;
+                    let date = NaiveDate::from_ymd_opt(
+                        start.year() + month.div_euclid(12) as i32,
+                        month.rem_euclid(12) as u32 + 1,
+                        start.day(),
+                    );
+                    if let Some(date) = date {
+                        return Some(date.and_time(start.time()));
+                    }
+                    periods -= 1;
+                }
+                return None;
+            }
+            Frequency::Weekly => 7 * 24 * 3600,
+            Frequency::Daily => 24 * 3600,
+            Frequency::Hourly => 3600,
+            Frequency::Minutely => 60,
+            Frequency::Secondly => 1,
+        } * interval;
+        let periods = (target - start).num_seconds().div_euclid(unit) - 1;
+        (periods > 0).then(|| start + Duration::seconds(periods * unit))
+    }
+}
+
+impl CustomTimezone {
+    pub(crate) fn new(
+        tzid: &str,
+        initial_offset: FixedOffset,
+        mut transitions: Vec<OffsetChange>,
+        recurring: Vec<RecurringTransition>,
+        lines: Vec<String>,
+    ) -> Self {
+        transitions.sort_by_key(|n| n.0);
+        transitions.dedup_by_key(|n| n.0);
+        let mut offsets = transitions
+            .iter()
+            .map(|n| n.1)
+            .chain(recurring.iter().map(|n| n.offset_to))
+            .chain(std::iter::once(initial_offset))
+            .collect::<Vec<_>>();
+        offsets.sort_by_key(|n| -n.local_minus_utc());
+        offsets.dedup();
+        CustomTimezone {
+            tzid: tzid.to_string(),
+            initial_offset,
+            transitions,
+            recurring,
+            offsets,
+            cache: Mutex::new(HashMap::new()),
+            lines,
+        }
+    }
+
+    /// 所有没有结束的规则在year年的切换，展开后缓存
+    fn recurring_in_year(&self, year: i32) -> Vec<OffsetChange> {
+        let mut cache = self.cache.lock().unwrap_or_else(|n| n.into_inner());
+        cache
+            .entry(year)
+            .or_insert_with(|| {
+                self.recurring
+                    .iter()
+                    .flat_map(|n| n.transitions_in_year(year))
+                    .collect()
+            })
+            .clone()
+    }
+
+    /// 没有结束的规则中不晚于utc的最后一次切换，早于after的不需要
+    fn last_recurring(
+        &self,
+        utc: &NaiveDateTime,
+        after: Option<&NaiveDateTime>,
+    ) -> Option<OffsetChange> {
+        let first_year = self.recurring.iter().map(|n| n.start.year()).min()?;
+        let last_year = after.map_or(first_year, |n| n.year().max(first_year));
+        let last_in_year = |year: i32| {
+            self.recurring_in_year(year)
+                .into_iter()
+                .filter(|n| n.0 <= *utc)
+                .max_by_key(|n| n.0)
+        };
+        // 当地时间的年份与UTC最多相差一年，找到后再看前一年，避免年初年末的切换顺序颠倒
+        let mut year = utc.year() + 1;
+        while year >= last_year {
+            if let Some(found) = last_in_year(year) {
+                return std::iter::once(found)
+                    .chain(last_in_year(year - 1))
+                    .max_by_key(|n| n.0);
+            }
+            year -= 1;
+        }
+        None
+    }
+
+    fn offset_from_utc(&self, utc: &NaiveDateTime) -> FixedOffset {
+        let last = match self.transitions.partition_point(|n| n.0 <= *utc) {
+            0 => None,
+            i => Some(self.transitions[i - 1]),
+        };
+        let recurring = self.last_recurring(utc, last.as_ref().map(|n| &n.0));
+        match (last, recurring) {
+            (Some(last), Some(recurring)) if last.0 > recurring.0 => last.1,
+            (_, Some(recurring)) => recurring.1,
+            (Some(last), None) => last.1,
+            (None, None) => self.initial_offset,
+        }
+    }
+
+    /// 用每个可能的偏移换算出UTC时间，再检查该时刻是否使用这个偏移
+    /// 夏令时开始时没有对应的时刻，结束时有两个
+    fn offset_from_local(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+        // 偏移越大，对应的UTC时刻越早
+        let list = self
+            .offsets
+            .iter()
+            .filter(|offset| {
+                let utc = *local - Duration::seconds(offset.local_minus_utc() as i64);
+                self.offset_from_utc(&utc) == **offset
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        match list[..] {
+            [] => LocalResult::None,
+            [offset] => LocalResult::Single(offset),
+            [earliest, .., latest] => LocalResult::Ambiguous(earliest, latest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_custom_offset_from_local() {
+        let time = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        let offset = |hours: i32| FixedOffset::east_opt(hours * 3600).unwrap();
+        // 3月26日01:00 UTC切换到+0200，10月29日01:00 UTC切换回+0100
+        let tz = CustomTimezone::new(
+            "Custom/Berlin",
+            offset(1),
+            vec![(time("2023-10-29 01:00:00"), offset(1)), (time("2023-03-26 01:00:00"), offset(2))],
+            vec![],
+            vec![],
+        );
+        assert_eq!(tz.offset_from_local(&time("2023-01-01 12:00:00")), LocalResult::Single(offset(1)));
+        assert_eq!(tz.offset_from_local(&time("2023-03-26 02:30:00")), LocalResult::None);
+        assert_eq!(tz.offset_from_local(&time("2023-07-01 12:00:00")), LocalResult::Single(offset(2)));
+        assert_eq!(
+            tz.offset_from_local(&time("2023-10-29 02:30:00")),
+            LocalResult::Ambiguous(offset(2), offset(1))
+        );
+        assert_eq!(tz.offset_from_utc(&time("2023-10-29 00:59:59")), offset(2));
+        assert_eq!(tz.offset_from_utc(&time("2023-10-29 01:00:00")), offset(1));
+
+        let tz = Timezone::from(tz);
+        assert_eq!(tz.name(), "Custom/Berlin");
+        assert_ne!(tz, Timezone::UTC);
+        assert_eq!(Timezone::from(Tz::Asia__Shanghai), Tz::Asia__Shanghai);
+    }
+
+    #[test]
+    fn test_recurring_transition() {
+        let offset = |hours: i32| FixedOffset::east_opt(hours * 3600).unwrap();
+        for (rrule, start) in [
+            ("FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU", "16010101T020000"),
+            ("FREQ=YEARLY;INTERVAL=3;BYMONTH=3,10;BYDAY=-1SU", "16010325T020000"),
+            ("FREQ=MONTHLY;INTERVAL=5", "19000131T020000"),
+            ("FREQ=WEEKLY;INTERVAL=2;BYDAY=SU;BYSETPOS=1", "19000107T020000"),
+        ] {
+            let start = start.parse::<PointTime>().unwrap();
+            let rule = RecurringTransition {
+                rrule: RRule::from_str(rrule).unwrap(),
+                start: start.to_naive_date_time().unwrap(),
+                offset_from: offset(1),
+                offset_to: offset(2),
+            };
+            // 按年展开的结果与从DTSTART展开的相同
+            let all = RRuleIter::new(&rule.rrule, &start)
+                .take_while(|n| n.year <= 2030)
+                .map(|n| n.to_naive_date_time().unwrap() - Duration::hours(1))
+                .collect::<Vec<_>>();
+            for year in [1600, 1601, 1602, 1950, 2023, 2024, 2030] {
+                let expected = all.iter().filter(|n| (**n + Duration::hours(1)).year() == year);
+                let list = rule.transitions_in_year(year);
+                assert_eq!(list.iter().map(|n| n.0).collect::<Vec<_>>(), expected.copied().collect::<Vec<_>>(), "{} {}", rrule, year);
+                assert!(list.iter().all(|n| n.1 == offset(2)));
+            }
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use chrono::Duration;
+use chrono_tz::Tz;
+use rrule_rust::{
+    ical::{parse_vcalendar, parse_vtimezone, to_vcalendar, to_vtimezone, EventProps},
+    point_time::PointTime,
+    rrule_set::RRuleSet,
+    timezone::Timezone,
+    RRuleError,
+};
+use std::str::FromStr;
+
+fn to_times(list: Vec<&str>, tz: Tz) -> Vec<chrono::DateTime<Tz>> {
+    list.iter()
+        .map(|time| time.parse::<PointTime>().unwrap().with_timezone(&tz))
+        .collect()
+}
+
+#[test]
+fn test_parse_vcalendar() {
+    let ics = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Example//EN\r
+BEGIN:VEVENT\r
+UID:event-1@example.com\r
+SUMMARY:Weekly sync\\, team\\; all\r
+DTSTART;TZID=\"America/New_York\":20231023T090000\r
+DTEND;TZID=America/New_York:20231023T093000\r
+RRULE:FREQ=WEEKLY;COUNT=4;\r
+ BYDAY=MO\r
+EXDATE;TZID=America/New_York:20231030T090000\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:event-1@example.com\r
+RECURRENCE-ID;TZID=America/New_York:20231106T090000\r
+DTSTART;TZID=America/New_York:20231107T100000\r
+DURATION:PT1H\r
+SUMMARY:Weekly sync moved\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:event-2@example.com\r
+DTSTART;VALUE=DATE:20231101\r
+DURATION:P1D\r
+RDATE;VALUE=DATE:20231105,20231110\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+    let events = parse_vcalendar(ics).unwrap();
+    assert_eq!(events.len(), 2);
+
+    let event = &events[0];
+    assert_eq!(event.uid.as_deref(), Some("event-1@example.com"));
+    assert_eq!(event.summary.as_deref(), Some("Weekly sync, team; all"));
+    assert_eq!(event.rrule_set.tz, Tz::America__New_York);
+    assert_eq!(
+        event.dt_end,
+        Some("20231023T093000".parse::<PointTime>().unwrap().with_timezone(&Timezone::from(Tz::America__New_York)))
+    );
+    assert_eq!(
+        event.rrule_set.all(),
+        to_times(vec!["20231023T090000", "20231106T090000", "20231113T090000"], Tz::America__New_York)
+    );
+    assert_eq!(event.overrides.len(), 1);
+    assert_eq!(event.overrides[0].summary.as_deref(), Some("Weekly sync moved"));
+    assert_eq!(event.overrides[0].duration, Some(Duration::hours(1)));
+    assert_eq!(
+        event.all(),
+        to_times(vec!["20231023T090000", "20231107T100000", "20231113T090000"], Tz::America__New_York)
+    );
+
+    let event = &events[1];
+    assert_eq!(event.duration, Some(Duration::days(1)));
+    assert!(event.overrides.is_empty());
+    assert_eq!(
+        event.all(),
+        to_times(vec!["20231101T000000", "20231105T000000", "20231110T000000"], Tz::UTC)
+    );
+}
+
+#[test]
+fn test_parse_vcalendar_error() {
+    assert_eq!(
+        parse_vcalendar("BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART:20231023T090000Z\nEND:VCALENDAR").err(),
+        Some(RRuleError::InvalidRule("VEVENT is not closed".to_string()))
+    );
+    assert_eq!(
+        parse_vcalendar("BEGIN:VEVENT\nDTSTART;TZID=Mars/Olympus:20231023T090000\nEND:VEVENT").err(),
+        Some(RRuleError::InvalidTimezone("Mars/Olympus".to_string()))
+    );
+    assert!(parse_vcalendar("BEGIN:VEVENT\nDURATION:1H\nEND:VEVENT").is_err());
+}
+
+#[test]
+fn test_to_vcalendar() {
+    let set = RRuleSet::from_str(
+        "DTSTART;TZID=America/New_York:20231023T090000\nRRULE:FREQ=WEEKLY;COUNT=4;BYDAY=MO\nEXDATE;TZID=America/New_York:20231030T090000",
+    )
+    .unwrap();
+    let props = EventProps {
+        uid: "event-1@example.com".to_string(),
+        summary: Some("Weekly sync, team; all".to_string()),
+        dt_end: Some("20231023T093000".parse().unwrap()),
+        dt_stamp: Some("20231001T000000Z".parse().unwrap()),
+    };
+    let ics = to_vcalendar(&set, &props);
+    assert_eq!(
+        ics,
+        "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//rrule-rust//rrule-rust//EN\r
+BEGIN:VTIMEZONE\r
+TZID:America/New_York\r
+BEGIN:STANDARD\r
+DTSTART:20230101T000000\r
+TZOFFSETFROM:-0500\r
+TZOFFSETTO:-0500\r
+TZNAME:EST\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:20230312T020000\r
+TZOFFSETFROM:-0500\r
+TZOFFSETTO:-0400\r
+TZNAME:EDT\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:20231105T020000\r
+TZOFFSETFROM:-0400\r
+TZOFFSETTO:-0500\r
+TZNAME:EST\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:event-1@example.com\r
+DTSTAMP:20231001T000000Z\r
+DTSTART;TZID=America/New_York:20231023T090000\r
+DTEND;TZID=America/New_York:20231023T093000\r
+SUMMARY:Weekly sync\\, team\\; all\r
+RRULE:FREQ=WEEKLY;COUNT=4;BYDAY=MO;WKST=SU\r
+EXDATE;TZID=America/New_York:20231030T090000\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+    );
+
+    let events = parse_vcalendar(&ics).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].uid.as_deref(), Some("event-1@example.com"));
+    assert_eq!(events[0].summary.as_deref(), Some("Weekly sync, team; all"));
+    assert_eq!(events[0].rrule_set.all(), set.all());
+    assert_eq!(
+        events[0].dt_end,
+        Some("20231023T093000".parse::<PointTime>().unwrap().with_timezone(&Timezone::from(Tz::America__New_York)))
+    );
+}
+
+#[test]
+fn test_to_vcalendar_fold() {
+    let set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;COUNT=2").unwrap();
+    let summary = "每周例会 weekly sync ".repeat(8);
+    let props = EventProps {
+        uid: "event-2@example.com".to_string(),
+        summary: Some(summary.clone()),
+        ..Default::default()
+    };
+    let ics = to_vcalendar(&set, &props);
+    assert!(ics.ends_with("\r\n"));
+    assert!(ics.contains("DTSTAMP:20231023T090000Z\r\n"));
+    assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+    let events = parse_vcalendar(&ics).unwrap();
+    assert_eq!(events[0].summary.as_deref(), Some(&summary[..]));
+}
+
+#[test]
+fn test_all_day_event() {
+    let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:birthday@example.com\r
+DTSTART;VALUE=DATE:20231115\r
+DTEND;VALUE=DATE:20231116\r
+RRULE:FREQ=YEARLY;UNTIL=20251115\r
+SUMMARY:Birthday\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+    let event = &parse_vcalendar(ics).unwrap()[0];
+    assert!(event.rrule_set.is_all_day());
+    assert_eq!(event.rrule_set.all_dates().len(), 3);
+    assert_eq!(event.dt_end, Some("20231116T000000".parse::<PointTime>().unwrap().with_timezone(&Timezone::UTC)));
+
+    let props = EventProps {
+        uid: "birthday@example.com".to_string(),
+        dt_end: Some("20231116".parse().unwrap()),
+        ..Default::default()
+    };
+    let ics = to_vcalendar(&event.rrule_set, &props);
+    assert!(ics.contains("\r\nDTSTART;VALUE=DATE:20231115\r\nDTEND;VALUE=DATE:20231116\r\nRRULE:FREQ=YEARLY;UNTIL=20251115;WKST=SU\r\n"));
+    assert_eq!(parse_vcalendar(&ics).unwrap()[0].rrule_set.all_dates(), event.rrule_set.all_dates());
+}
+
+#[test]
+fn test_to_vtimezone() {
+    let vtimezone = |s: &str| to_vtimezone(&RRuleSet::from_str(s).unwrap()).replace("\r\n", "\n");
+
+    // 没有结束的序列，切换规则不带UNTIL
+    assert_eq!(
+        vtimezone("DTSTART;TZID=Europe/London:20231023T090000\nRRULE:FREQ=WEEKLY"),
+        "BEGIN:VTIMEZONE
+TZID:Europe/London
+BEGIN:STANDARD
+DTSTART:20230101T000000
+TZOFFSETFROM:+0000
+TZOFFSETTO:+0000
+TZNAME:GMT
+END:STANDARD
+BEGIN:DAYLIGHT
+DTSTART:20230326T010000
+TZOFFSETFROM:+0000
+TZOFFSETTO:+0100
+TZNAME:BST
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU
+END:DAYLIGHT
+BEGIN:STANDARD
+DTSTART:20231029T020000
+TZOFFSETFROM:+0100
+TZOFFSETTO:+0000
+TZNAME:GMT
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU
+END:STANDARD
+END:VTIMEZONE
+"
+    );
+
+    // 2007年美国修改了夏令时的规则，旧规则带UNTIL
+    let s = vtimezone("DTSTART;TZID=America/New_York:20050101T090000\nRRULE:FREQ=YEARLY;COUNT=4");
+    assert!(s.contains("RRULE:FREQ=YEARLY;BYMONTH=4;BYDAY=1SU;UNTIL=20060402T070000Z\n"));
+    assert!(s.contains("RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU;UNTIL=20061029T060000Z\n"));
+    assert!(s.contains("DTSTART:20070311T020000\nTZOFFSETFROM:-0500\nTZOFFSETTO:-0400\nTZNAME:EDT\nRRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=2SU\n"));
+    assert!(s.contains("DTSTART:20071104T020000\nTZOFFSETFROM:-0400\nTZOFFSETTO:-0500\nTZNAME:EST\nRRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=1SU\n"));
+
+    assert_eq!(
+        vtimezone("DTSTART;TZID=Asia/Shanghai:20231023T090000\nRRULE:FREQ=DAILY"),
+        "BEGIN:VTIMEZONE
+TZID:Asia/Shanghai
+BEGIN:STANDARD
+DTSTART:20230101T000000
+TZOFFSETFROM:+0800
+TZOFFSETTO:+0800
+TZNAME:CST
+END:STANDARD
+END:VTIMEZONE
+"
+    );
+    assert_eq!(vtimezone("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY"), "");
+}
+
+#[test]
+fn test_custom_vtimezone() {
+    let ics = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:W. Europe Standard Time\r
+BEGIN:STANDARD\r
+DTSTART:16010101T030000\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=-1SU;BYMONTH=10\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:16010101T020000\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=-1SU;BYMONTH=3\r
+END:DAYLIGHT\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:event-3@example.com\r
+DTSTART;TZID=W. Europe Standard Time:20231023T090000\r
+DTEND;TZID=\"W. Europe Standard Time\":20231023T100000\r
+RRULE:FREQ=WEEKLY;COUNT=3;BYDAY=MO\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+    let events = parse_vcalendar(ics).unwrap();
+    let set = &events[0].rrule_set;
+    assert_eq!(set.tz.name(), "W. Europe Standard Time");
+    // 10月29日切换为标准时间，当地时间不变，UTC时间晚一个小时
+    let utc = set
+        .all()
+        .iter()
+        .map(|n| n.naive_utc().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(utc, vec!["2023-10-23 07:00:00", "2023-10-30 08:00:00", "2023-11-06 08:00:00"]);
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231023T090000", "20231030T090000", "20231106T090000"], Tz::Europe__Berlin)
+    );
+    assert_eq!(events[0].dt_end.as_ref().map(|n| n.naive_utc().to_string()).as_deref(), Some("2023-10-23 08:00:00"));
+
+    // 导出时原样输出VTIMEZONE
+    let ics = to_vcalendar(set, &EventProps { uid: "event-3@example.com".to_string(), ..Default::default() });
+    assert!(ics.contains("BEGIN:VTIMEZONE\r\nTZID:W. Europe Standard Time\r\nBEGIN:STANDARD\r\nDTSTART:16010101T030000\r\n"));
+    assert!(ics.contains("DTSTART;TZID=W. Europe Standard Time:20231023T090000\r\n"));
+    assert_eq!(parse_vcalendar(&ics).unwrap()[0].rrule_set.all(), set.all());
+
+    // 没有结束的规则按年展开，与Europe/Berlin一致，包括夏令时切换的当天
+    let mut set = RRuleSet::from_str("DTSTART:20100101T023000\nRRULE:FREQ=DAILY;COUNT=5000").unwrap();
+    set.tz("Europe/Berlin").unwrap();
+    let berlin = set.all().iter().map(|n| n.timestamp()).collect::<Vec<_>>();
+    set.set_timezone(events[0].rrule_set.tz.clone());
+    assert_eq!(set.all().iter().map(|n| n.timestamp()).collect::<Vec<_>>(), berlin);
+    let mut set = RRuleSet::from_str("DTSTART:20100101T090000\nRRULE:FREQ=MONTHLY;BYMONTH=7,12").unwrap();
+    set.set_timezone(events[0].rrule_set.tz.clone());
+    set.set_between("28000101T000000", "28001231T000000").unwrap();
+    let utc = set.all().iter().map(|n| n.naive_utc().to_string()).collect::<Vec<_>>();
+    assert_eq!(utc, vec!["2800-07-01 07:00:00", "2800-12-01 08:00:00"]);
+}
+
+#[test]
+fn test_parse_vtimezone() {
+    let tz: Timezone = parse_vtimezone(
+        "BEGIN:VTIMEZONE
+TZID:Custom/Fixed
+BEGIN:STANDARD
+DTSTART:19700101T000000
+TZOFFSETFROM:+0530
+TZOFFSETTO:+0530
+TZNAME:IST
+END:STANDARD
+END:VTIMEZONE",
+    )
+    .unwrap()
+    .into();
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000\nRRULE:FREQ=DAILY;COUNT=2").unwrap();
+    set.set_timezone(tz.clone());
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231023T090000", "20231024T090000"], Tz::Asia__Kolkata)
+    );
+    let set = RRuleSet::from_str_with_timezones("DTSTART;TZID=Custom/Fixed:20231023T090000\nRRULE:FREQ=DAILY;COUNT=1", &[tz]).unwrap();
+    assert_eq!(set.all(), to_times(vec!["20231023T090000"], Tz::Asia__Kolkata));
+
+    assert_eq!(
+        parse_vtimezone("BEGIN:VTIMEZONE\nTZID:A\nBEGIN:STANDARD\nDTSTART:19700101T000000\nTZOFFSETFROM:0530\nTZOFFSETTO:+0530\nEND:STANDARD\nEND:VTIMEZONE").err(),
+        Some(RRuleError::InvalidValue("TZOFFSETFROM".to_string(), "0530".to_string()))
+    );
+    // 偏移不能达到24小时
+    assert_eq!(
+        parse_vtimezone("BEGIN:VTIMEZONE\nTZID:A\nBEGIN:STANDARD\nDTSTART:19700101T000000\nTZOFFSETFROM:+0000\nTZOFFSETTO:+2500\nEND:STANDARD\nEND:VTIMEZONE").err(),
+        Some(RRuleError::InvalidValue("TZOFFSETTO".to_string(), "+2500".to_string()))
+    );
+    assert_eq!(
+        parse_vtimezone("BEGIN:VTIMEZONE\nTZID:A\nBEGIN:STANDARD\nDTSTART:19700101T000000\nTZOFFSETTO:+0530\nEND:STANDARD\nEND:VTIMEZONE").err(),
+        Some(RRuleError::InvalidRule("TZOFFSETFROM is required in STANDARD".to_string()))
+    );
+    assert!(parse_vtimezone("BEGIN:VTIMEZONE\nTZID:A\nEND:VTIMEZONE").is_err());
+    assert!(RRuleSet::from_str("DTSTART;TZID=Custom/Fixed:20231023T090000").is_err());
+}
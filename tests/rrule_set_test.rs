@@ -1,5 +1,18 @@
+use chrono::{DateTime, NaiveDate, TimeZone};
 use chrono_tz::Tz;
-use rrule_rust::{point_time::PointTime, rrule_set::RRuleSet};
+use rrule_rust::{
+    point_time::{AmbiguousTime, DstPolicy, NonexistentTime, PointTime, TimeForm},
+    rrule_set::{ExpandOptions, RRuleSet},
+    timezone::Timezone,
+    RRuleError,
+};
+use std::str::FromStr;
+
+fn to_times<T: TimeZone>(list: Vec<&str>, tz: T) -> Vec<DateTime<T>> {
+    list.iter()
+        .map(|time| time.parse::<PointTime>().unwrap().with_timezone(&tz))
+        .collect()
+}
 
 fn run_test_by_vec(test_vec: Vec<(&str, Vec<&str>)>) {
     test_vec.iter().for_each(|(str, vec)| {
@@ -59,6 +72,102 @@ fn test_expand_by_day() {
     run_test_by_vec(test_vec);
 }
 
+#[test]
+fn test_expand_by_hour() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=HOURLY;COUNT=3;INTERVAL=5",
+            vec!["20231023T180000", "20231023T230000", "20231024T040000"],
+        ),
+        (
+            "DTSTART:20231022T220000Z\nRRULE:FREQ=HOURLY;COUNT=4;BYDAY=MO",
+            vec!["20231023T000000", "20231023T010000", "20231023T020000", "20231023T030000"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=HOURLY;COUNT=3;INTERVAL=5;BYDAY=TU",
+            vec!["20231024T040000", "20231024T090000", "20231024T140000"],
+        ),
+        (
+            "DTSTART:20231023T220000Z\nRRULE:FREQ=HOURLY;UNTIL=20231024T010000",
+            vec!["20231023T220000", "20231023T230000", "20231024T000000", "20231024T010000"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+}
+
+#[test]
+fn test_expand_by_minute() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=MINUTELY;COUNT=3;INTERVAL=15",
+            vec!["20231023T180000", "20231023T181500", "20231023T183000"],
+        ),
+        (
+            "DTSTART:20231031T235500Z\nRRULE:FREQ=MINUTELY;COUNT=3;INTERVAL=7;BYMONTHDAY=1",
+            vec!["20231101T000200", "20231101T000900", "20231101T001600"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+}
+
+#[test]
+fn test_expand_by_second() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=SECONDLY;COUNT=3;INTERVAL=30",
+            vec!["20231023T180000", "20231023T180030", "20231023T180100"],
+        ),
+        (
+            "DTSTART:20231130T235959Z\nRRULE:FREQ=SECONDLY;COUNT=2;BYMONTH=12",
+            vec!["20231201T000000", "20231201T000001"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+}
+
+#[test]
+fn test_expand_by_time() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=6;BYHOUR=9,14;BYMINUTE=0,30",
+            vec!["20231024T090000", "20231024T093000", "20231024T140000", "20231024T143000", "20231025T090000", "20231025T093000"],
+        ),
+        (
+            "DTSTART:20231023T100000Z\nRRULE:FREQ=DAILY;COUNT=3;BYHOUR=9,14",
+            vec!["20231023T140000", "20231024T090000", "20231024T140000"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=WEEKLY;COUNT=4;BYDAY=MO,WE;BYHOUR=8;BYMINUTE=15",
+            vec!["20231025T081500", "20231030T081500", "20231101T081500", "20231106T081500"],
+        ),
+        (
+            "DTSTART:20231029T091800Z\nRRULE:FREQ=MONTHLY;COUNT=3;BYHOUR=7,20",
+            vec!["20231029T201800", "20231129T071800", "20231129T201800"],
+        ),
+        (
+            "DTSTART:20231029T091800Z\nRRULE:FREQ=MONTHLY;COUNT=3;BYMONTHDAY=1;BYHOUR=7,20;BYSECOND=5",
+            vec!["20231101T071805", "20231101T201805", "20231201T071805"],
+        ),
+        (
+            "DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=3;BYMINUTE=0,45",
+            vec!["20231123T094500", "20241123T090000", "20241123T094500"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=HOURLY;COUNT=4;BYHOUR=20,21;BYMINUTE=0,30",
+            vec!["20231023T200000", "20231023T203000", "20231023T210000", "20231023T213000"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=MINUTELY;COUNT=3;INTERVAL=20;BYHOUR=19;BYSECOND=10,20",
+            vec!["20231023T190010", "20231023T190020", "20231023T192010"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=SECONDLY;COUNT=3;BYMINUTE=1;BYSECOND=0,30",
+            vec!["20231023T180100", "20231023T180130", "20231023T190100"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+}
+
 #[test]
 fn test_expand_by_week() {
     let test_vec = vec![
@@ -149,13 +258,681 @@ fn test_expand_by_month() {
     run_test_by_vec(test_vec);
 }
 
+#[test]
+fn test_by_set_pos() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231029T091800Z\nRRULE:FREQ=MONTHLY;COUNT=3;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1",
+            vec!["20231031T091800", "20231130T091800", "20231229T091800"],
+        ),
+        (
+            "DTSTART:20231029T091800Z\nRRULE:FREQ=MONTHLY;COUNT=4;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=1,-1",
+            vec!["20231031T091800", "20231101T091800", "20231130T091800", "20231201T091800"],
+        ),
+        (
+            "DTSTART:20231029T091800Z\nRRULE:FREQ=MONTHLY;COUNT=3;BYMONTHDAY=1,15,-1;BYSETPOS=2",
+            vec!["20231115T091800", "20231215T091800", "20240115T091800"],
+        ),
+        (
+            "DTSTART:20231029T091800Z\nRRULE:FREQ=MONTHLY;COUNT=3;BYDAY=SA,SU;BYMONTH=3;BYSETPOS=-2",
+            vec!["20240330T091800", "20250329T091800", "20260328T091800"],
+        ),
+        (
+            "DTSTART:20231023T091800Z\nRRULE:FREQ=MONTHLY;UNTIL=20231216T000000;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1",
+            vec!["20231031T091800", "20231130T091800"],
+        ),
+        (
+            "DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=3;BYDAY=MO;BYMONTH=5;BYSETPOS=-1",
+            vec!["20240527T091800", "20250526T091800", "20260525T091800"],
+        ),
+        (
+            "DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=3;BYMONTHDAY=1;BYMONTH=1,7;BYSETPOS=2",
+            vec!["20240701T091800", "20250701T091800", "20260701T091800"],
+        ),
+        (
+            "DTSTART:20231025T091800Z\nRRULE:FREQ=WEEKLY;COUNT=3;BYDAY=MO,WE,FR;BYSETPOS=1",
+            vec!["20231025T091800", "20231030T091800", "20231106T091800"],
+        ),
+        (
+            "DTSTART:20231023T091800Z\nRRULE:FREQ=WEEKLY;COUNT=3;WKST=MO;BYDAY=MO,WE,FR;BYSETPOS=-1;INTERVAL=2",
+            vec!["20231027T091800", "20231110T091800", "20231124T091800"],
+        ),
+        (
+            "DTSTART:20231023T091800Z\nRRULE:FREQ=DAILY;COUNT=3;BYHOUR=9,12,18;BYSETPOS=-1",
+            vec!["20231023T181800", "20231024T181800", "20231025T181800"],
+        ),
+        (
+            "DTSTART:20231023T090000Z\nRRULE:FREQ=HOURLY;COUNT=3;BYMINUTE=0,20,40;BYSETPOS=2;INTERVAL=3",
+            vec!["20231023T092000", "20231023T122000", "20231023T152000"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+}
+
+#[test]
+fn test_multiple_rrule() {
+    let mut set = RRuleSet::from_str("DTSTART:20231023T180000Z\nRRULE:FREQ=WEEKLY;COUNT=3;BYDAY=MO").unwrap();
+    set.add_rrule("RRULE:FREQ=DAILY;COUNT=3;INTERVAL=7").unwrap();
+    set.add_rrule("RRULE:FREQ=MONTHLY;COUNT=2;BYMONTHDAY=1").unwrap();
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231023T180000", "20231030T180000", "20231101T180000", "20231106T180000", "20231201T180000"], Tz::UTC)
+    );
+    assert_eq!(
+        set.to_string(),
+        "DTSTART:20231023T180000Z\nRRULE:FREQ=WEEKLY;COUNT=3;BYDAY=MO;WKST=SU\nRRULE:FREQ=DAILY;COUNT=3;INTERVAL=7;WKST=SU\nRRULE:FREQ=MONTHLY;COUNT=2;WKST=SU;BYMONTHDAY=1"
+    );
+
+    set.set_rrule_count(2, 1);
+    set.set_rrule_until(1, "20231024T000000").unwrap();
+    set.set_rrule_count(1, 0);
+    assert_eq!(set.rrule[2].count, 1);
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231023T180000", "20231030T180000", "20231101T180000", "20231106T180000"], Tz::UTC)
+    );
+}
+
+#[test]
+fn test_exdate() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=4\nEXDATE:20231024T180000Z,20231026T180000Z",
+            vec!["20231023T180000", "20231025T180000"],
+        ),
+        (
+            "EXDATE:20231024T180000Z\nDTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=3\nEXDATE:20231024T170000Z",
+            vec!["20231023T180000", "20231025T180000"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=3\nEXDATE;TZID=Asia/Shanghai:20231025T020000",
+            vec!["20231023T180000", "20231025T180000"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+
+    let mut set = RRuleSet::from_str(
+        "DTSTART;TZID=America/New_York:20231023T180000\nRRULE:FREQ=DAILY;COUNT=3\nEXDATE;TZID=Asia/Shanghai:20231025T060000",
+    )
+    .unwrap();
+    assert_eq!(set.exdate, vec!["20231024T180000".parse::<PointTime>().unwrap()]);
+    assert_eq!(
+        set.to_string(),
+        "DTSTART;TZID=America/New_York:20231023T180000\nRRULE:FREQ=DAILY;COUNT=3;WKST=SU\nEXDATE;TZID=America/New_York:20231024T180000"
+    );
+
+    set.add_exdate("20231025T180000").unwrap();
+    set.remove_exdate("20231024T180000").unwrap();
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231023T180000", "20231024T180000"], Tz::America__New_York)
+    );
+
+    // 带Z的是UTC时间，转换到set的时区下
+    let mut set = RRuleSet::from_str("DTSTART;TZID=America/New_York:20231023T090000\nRRULE:FREQ=DAILY;COUNT=3").unwrap();
+    set.add_exdate("20231024T130000Z").unwrap();
+    assert_eq!(set.all(), to_times(vec!["20231023T090000", "20231025T090000"], Tz::America__New_York));
+    assert_eq!(
+        set.to_string(),
+        "DTSTART;TZID=America/New_York:20231023T090000\nRRULE:FREQ=DAILY;COUNT=3;WKST=SU\nEXDATE;TZID=America/New_York:20231024T090000"
+    );
+    // 删除时与EXDATE行和add_exdate的写法一致
+    let mut set = RRuleSet::from_str("DTSTART;TZID=America/New_York:20231023T090000\nRRULE:FREQ=DAILY;COUNT=3\nEXDATE:20231024T130000Z").unwrap();
+    assert_eq!(set.all().len(), 2);
+    set.remove_exdate("20231024T130000Z").unwrap();
+    assert!(set.exdate.is_empty());
+    assert_eq!(set.all().len(), 3);
+}
+
+#[test]
+fn test_rdate() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=2\nRDATE:20231101T090000Z,20231023T180000Z",
+            vec!["20231023T180000", "20231024T180000", "20231101T090000"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=2\nRDATE;VALUE=DATE:20231020,20231102",
+            vec!["20231020T000000", "20231023T180000", "20231024T180000", "20231102T000000"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=1\nRDATE;VALUE=PERIOD:20231101T090000Z/20231101T100000Z,20231105T120000Z/PT1H",
+            vec!["20231023T180000", "20231101T090000", "20231105T120000"],
+        ),
+        (
+            "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=1\nRDATE;TZID=Asia/Shanghai;VALUE=DATE-TIME:20231102T080000\nEXDATE:20231023T180000Z",
+            vec!["20231102T000000"],
+        ),
+        (
+            "RDATE:20231102T080000Z,20231101T080000Z",
+            vec!["20231101T080000", "20231102T080000"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+
+    let mut set = RRuleSet::from_str("DTSTART;TZID=Asia/Shanghai:20231023T180000\nRRULE:FREQ=DAILY;COUNT=1").unwrap();
+    set.add_rdate("20231101T090000").unwrap();
+    assert_eq!(
+        set.to_string(),
+        "DTSTART;TZID=Asia/Shanghai:20231023T180000\nRRULE:FREQ=DAILY;COUNT=1;WKST=SU\nRDATE;TZID=Asia/Shanghai:20231101T090000"
+    );
+    assert_eq!(set.all().len(), 2);
+    // 带Z的RDATE和DTSTART转换为上海时间
+    set.add_rdate("20231102T010000Z").unwrap();
+    set.set_dt_start("20231023T100000Z").unwrap();
+    assert_eq!(
+        set.to_string(),
+        "DTSTART;TZID=Asia/Shanghai:20231023T180000\nRRULE:FREQ=DAILY;COUNT=1;WKST=SU\nRDATE;TZID=Asia/Shanghai:20231101T090000,20231102T090000"
+    );
+}
+
+#[test]
+fn test_exrule() {
+    let test_vec = vec![
+        (
+            "DTSTART:20231030T090000Z\nRRULE:FREQ=WEEKLY;COUNT=10;BYDAY=MO,TU,WE,TH,FR\nEXRULE:FREQ=MONTHLY;COUNT=2;BYDAY=1MO",
+            vec![
+                "20231030T090000", "20231031T090000", "20231101T090000", "20231102T090000", "20231103T090000",
+                "20231107T090000", "20231108T090000", "20231109T090000", "20231110T090000",
+            ],
+        ),
+        (
+            "DTSTART:20231030T090000Z\nRRULE:FREQ=DAILY;COUNT=3\nRDATE:20231105T090000Z\nEXRULE:FREQ=WEEKLY;BYDAY=SU,TU;UNTIL=20231231T000000",
+            vec!["20231030T090000", "20231101T090000"],
+        ),
+    ];
+    run_test_by_vec(test_vec);
+
+    let mut set = RRuleSet::from_str("DTSTART:20231030T090000Z\nEXRULE:FREQ=DAILY;COUNT=1\nRRULE:FREQ=DAILY;COUNT=3").unwrap();
+    set.add_exrule("EXRULE:FREQ=DAILY;INTERVAL=2;COUNT=2").unwrap();
+    assert_eq!(
+        set.to_string(),
+        "DTSTART:20231030T090000Z\nRRULE:FREQ=DAILY;COUNT=3;WKST=SU\nEXRULE:FREQ=DAILY;COUNT=1;WKST=SU\nEXRULE:FREQ=DAILY;COUNT=2;INTERVAL=2;WKST=SU"
+    );
+    assert_eq!(
+        set.all(),
+        vec!["20231031T090000".parse::<PointTime>().unwrap().with_timezone(&Tz::UTC)]
+    );
+}
+
+#[test]
+fn test_iter() {
+    let set = RRuleSet::from_str(
+        "DTSTART:20231023T180000Z\nRRULE:FREQ=MINUTELY;UNTIL=22000101T000000\nRDATE:20231023T180030Z\nEXDATE:20231023T180100Z",
+    )
+    .unwrap();
+    assert_eq!(
+        set.iter().take(4).collect::<Vec<_>>(),
+        to_times(vec!["20231023T180000", "20231023T180030", "20231023T180200", "20231023T180300"], Tz::UTC)
+    );
+    let after = to_times(vec!["20231109T235830"], Tz::UTC).remove(0);
+    assert_eq!(
+        set.iter().skip_while(|n| n < &after).take(2).collect::<Vec<_>>(),
+        to_times(vec!["20231109T235900", "20231110T000000"], Tz::UTC)
+    );
+
+    let mut set = RRuleSet::from_str("DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=20;BYHOUR=9,18").unwrap();
+    set.add_rrule("RRULE:FREQ=WEEKLY;COUNT=5;BYDAY=TU,TH").unwrap();
+    set.add_exrule("EXRULE:FREQ=DAILY;COUNT=3;INTERVAL=2;BYHOUR=9").unwrap();
+    set.set_between("20231025T000000", "20231030T000000").unwrap();
+    assert_eq!(set.all(), set.iter().collect::<Vec<_>>());
+    assert_eq!(set.iter().count(), 7);
+}
+
+#[test]
+fn test_after_before_between() {
+    let tz = Timezone::Tz(Tz::America__New_York);
+    let str = "DTSTART;TZID=America/New_York:20231013T091800\nRRULE:FREQ=WEEKLY;BYDAY=FR;UNTIL=20231128T105959";
+    let set = RRuleSet::from_str(str).unwrap();
+
+    assert_eq!(set.after("20231103T091800", false).unwrap(), to_times(vec!["20231110T091800"], tz.clone()).pop());
+    assert_eq!(set.after("20231103T091800", true).unwrap(), to_times(vec!["20231103T091800"], tz.clone()).pop());
+    assert_eq!(set.after("20231124T091800", false).unwrap(), None);
+
+    assert_eq!(set.before("20231103T091800", false).unwrap(), to_times(vec!["20231027T091800"], tz.clone()).pop());
+    assert_eq!(set.before("20231103T091800", true).unwrap(), to_times(vec!["20231103T091800"], tz.clone()).pop());
+    assert_eq!(set.before("20231013T091800", false).unwrap(), None);
+
+    assert_eq!(
+        set.between("20231020T091800", "20231103T091800", false).unwrap(),
+        to_times(vec!["20231027T091800"], tz.clone())
+    );
+    assert_eq!(
+        set.between("20231020T091800", "20231103T091800", true).unwrap(),
+        to_times(vec!["20231020T091800", "20231027T091800", "20231103T091800"], tz.clone())
+    );
+
+    // 没有截止时间时也能尽早返回
+    let set = RRuleSet::from_str("DTSTART:20231023T180000Z\nRRULE:FREQ=SECONDLY;UNTIL=22000101T000000").unwrap();
+    assert_eq!(set.after("20231023T180000", false).unwrap(), to_times(vec!["20231023T180001"], Timezone::UTC).pop());
+}
+
+#[test]
+fn test_until_and_between_by_time() {
+    let tz = Timezone::Tz(Tz::Asia__Shanghai);
+    // UNTIL为10点时不包含当天18点的时间
+    let set = RRuleSet::from_str("DTSTART;TZID=Asia/Shanghai:20231023T180000\nRRULE:FREQ=DAILY;UNTIL=20231025T100000").unwrap();
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231023T180000", "20231024T180000"], tz.clone())
+    );
+    // UTC写法的UNTIL转换为当地时间后比较，20231025T100000Z是上海的18点
+    let set = RRuleSet::from_str("DTSTART;TZID=Asia/Shanghai:20231023T180000\nRRULE:FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20231025T100000Z").unwrap();
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231023T180000", "20231025T180000"], tz.clone())
+    );
+
+    let mut set = RRuleSet::from_str("DTSTART;TZID=Asia/Shanghai:20231023T180000\nRRULE:FREQ=DAILY;COUNT=5").unwrap();
+    set.set_between("20231024T100000", "20231026T180000").unwrap();
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231024T180000", "20231025T180000", "20231026T180000"], tz.clone())
+    );
+    // 带Z的范围是UTC时间，20231026T095959Z是上海的17:59:59
+    set.set_between("20231024T100000Z", "20231026T095959Z").unwrap();
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231024T180000", "20231025T180000"], tz.clone())
+    );
+    assert_eq!(set.after("20231024T100000Z", false).unwrap(), to_times(vec!["20231025T180000"], tz.clone()).pop());
+    assert_eq!(set.before("20231024T100000Z", true).unwrap(), to_times(vec!["20231024T180000"], tz.clone()).pop());
+}
+
+#[test]
+fn test_without_count_and_until() {
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=WEEKLY;BYDAY=MO").unwrap();
+    assert_eq!(
+        set.all_with_limit(3),
+        to_times(vec!["20231023T090000", "20231030T090000", "20231106T090000"], Timezone::UTC)
+    );
+    assert_eq!(set.iter().nth(100), to_times(vec!["20250922T090000"], Timezone::UTC).pop());
+    assert_eq!(set.after("29991231T000000", false).unwrap(), to_times(vec!["30000106T090000"], Timezone::UTC).pop());
+
+    set.set_between("20231101T000000", "20231201T000000").unwrap();
+    assert_eq!(set.all().len(), 4);
+
+    // 不会有结果的规则展开到MAX_YEAR为止
+    let set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30").unwrap();
+    assert!(set.all().is_empty());
+}
+
+#[test]
+fn test_expand_options() {
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY").unwrap();
+    let all = set.all();
+    assert_eq!((all.len(), all.truncated), (65535, true));
+    assert!(set.all_with_limit(3).truncated);
+
+    set.set_options(ExpandOptions {
+        max_results: 2,
+        ..Default::default()
+    });
+    assert_eq!(set.all(), to_times(vec!["20231023T090000", "20231024T090000"], Timezone::UTC));
+    assert!(set.all().truncated);
+
+    // 展开到horizon为止，带Z时为UTC时间
+    set.set_options(ExpandOptions {
+        horizon: Some("20231025T090000".parse().unwrap()),
+        ..Default::default()
+    });
+    assert_eq!(set.all().len(), 3);
+    assert!(set.all().truncated);
+    let mut set = RRuleSet::from_str("DTSTART;TZID=Asia/Shanghai:20231023T090000\nRRULE:FREQ=DAILY").unwrap();
+    set.set_options(ExpandOptions {
+        horizon: Some("20231025T005959Z".parse().unwrap()),
+        ..Default::default()
+    });
+    assert_eq!(set.all().len(), 2);
+    assert!(set.all().truncated);
+    assert_eq!(set.iter().count(), 2);
+    assert!(set.after("20231025T000000", false).unwrap().is_none());
+
+    // 在horizon之前结束的规则没有被截断
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;COUNT=3").unwrap();
+    set.set_options(ExpandOptions {
+        horizon: Some("20231025T090000".parse().unwrap()),
+        ..Default::default()
+    });
+    let all = set.all();
+    assert_eq!((all.len(), all.truncated), (3, false));
+    assert!(!set.all_with_limit(3).truncated);
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;UNTIL=20231025T090000\nEXRULE:FREQ=DAILY;BYMONTH=2").unwrap();
+    set.set_options(ExpandOptions {
+        horizon: Some("20231026T000000".parse().unwrap()),
+        ..Default::default()
+    });
+    assert!(!set.all().truncated);
+
+    // 不会有结果的规则最多展开max_iterations个周期
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;BYMONTH=2;BYMONTHDAY=30").unwrap();
+    set.set_options(ExpandOptions {
+        max_iterations: 1000,
+        ..Default::default()
+    });
+    let all = set.all();
+    assert_eq!((all.len(), all.truncated), (0, true));
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY").unwrap();
+    set.set_options(ExpandOptions {
+        max_iterations: 10,
+        ..Default::default()
+    });
+    assert_eq!(set.all().len(), 10);
+    assert!(set.all().truncated);
+
+    // EXRULE提前结束后，无法判断之后的时间是否被排除，不再返回
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=WEEKLY;COUNT=3\nEXRULE:FREQ=DAILY").unwrap();
+    assert!(set.all().is_empty());
+    set.set_options(ExpandOptions {
+        max_iterations: 5,
+        ..Default::default()
+    });
+    let all = set.all();
+    assert_eq!((all.len(), all.truncated), (0, true));
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=WEEKLY;COUNT=10;BYDAY=SU,MO,TU,WE,TH,FR,SA\nEXRULE:FREQ=HOURLY;INTERVAL=48").unwrap();
+    set.set_options(ExpandOptions {
+        max_iterations: 3,
+        ..Default::default()
+    });
+    let all = set.all();
+    assert_eq!(all, to_times(vec!["20231024T090000", "20231026T090000", "20231028T090000"], Timezone::UTC));
+    assert!(all.truncated);
+}
+
+#[test]
+fn test_invalid_str() {
+    assert_eq!(
+        RRuleSet::from_str("DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT").err(),
+        Some(RRuleError::InvalidProperty("COUNT".to_string()))
+    );
+    assert_eq!(
+        RRuleSet::from_str("DTSTART;TZID=Mars/Olympus:20231023T180000\nRRULE:FREQ=DAILY").err(),
+        Some(RRuleError::InvalidTimezone("Mars/Olympus".to_string()))
+    );
+    assert_eq!(
+        RRuleSet::from_str("DTSTART:2023102T180000Z\nRRULE:FREQ=DAILY").err(),
+        Some(RRuleError::InvalidDate("2023102T180000Z".to_string()))
+    );
+    assert_eq!(
+        RRuleSet::from_str("RDATE;VALUE=ABC:20231023").err(),
+        Some(RRuleError::Unsupported("VALUE=ABC".to_string()))
+    );
+    // 超出范围的值在解析时返回错误，展开时不会panic
+    for (property, value) in [
+        ("BYMONTH", "13"),
+        ("BYMONTHDAY", "-32"),
+        ("BYYEARDAY", "0"),
+        ("BYWEEKNO", "54"),
+        ("BYSETPOS", "-32768"),
+        ("BYSETPOS", "367"),
+    ] {
+        let str = format!("DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;{}={};BYDAY=-1FR;COUNT=1", property, value);
+        assert_eq!(
+            RRuleSet::from_str(&str).err(),
+            Some(RRuleError::InvalidValue(property.to_string(), value.to_string()))
+        );
+    }
+    let set = RRuleSet::from_str("DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;BYDAY=MO;BYSETPOS=-366,366;COUNT=1").unwrap();
+    assert!(set.all().is_empty());
+    assert!(RRuleSet::from_json("{").is_err());
+
+    let mut set = RRuleSet::from_str("DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=3").unwrap();
+    assert!(set.add_rrule("RRULE:FREQ=DAILY;BYDAY=1").is_err());
+    assert!(set.tz("America/Gotham").is_err());
+    assert!(set.after("2023", false).is_err());
+    assert!(set.set_dt_start("2023102").is_err());
+    assert!(set.set_until("20231323T000000").is_err());
+    assert!(set.set_between("20231023T000000", "2023").is_err());
+    assert_eq!(set.set_between("2023", "20231023T000000").unwrap_err().code(), "INVALID_DATE");
+    assert_eq!(set.all().len(), 3);
+}
+
+#[test]
+fn test_from_str_strict() {
+    let str = "DTSTART:20231023T180000Z\nRRULE:FREQ=WEEKLY;COUNT=3;BYDAY=MO\nEXRULE:FREQ=MONTHLY;BYMONTHDAY=32";
+    assert_eq!(
+        RRuleSet::from_str_strict(str).err(),
+        Some(RRuleError::InvalidValue("BYMONTHDAY".to_string(), "32".to_string()))
+    );
+    assert_eq!(RRuleSet::from_str_strict(str).err(), RRuleSet::from_str(str).err());
+    // 默认模式不校验属性的组合
+    let str = "DTSTART:20231023T180000Z\nRRULE:FREQ=MONTHLY;COUNT=3;BYWEEKNO=1";
+    assert_eq!(
+        RRuleSet::from_str_strict(str).err(),
+        Some(RRuleError::InvalidRule("BYWEEKNO is not valid with FREQ=MONTHLY".to_string()))
+    );
+    assert!(RRuleSet::from_str(str).is_ok());
+
+    let str = "DTSTART:20231023T180000Z\nRRULE:FREQ=WEEKLY;COUNT=3;BYDAY=MO";
+    assert_eq!(RRuleSet::from_str_strict(str).unwrap().all(), RRuleSet::from_str(str).unwrap().all());
+}
+
+#[test]
+fn test_from_str_lenient() {
+    let str = "dtstart;tzid=America/New_York: 20231023T090000\r\nRRULE:freq=WEEKLY;COUNT=3;;\r\n\r\nexdate : 20231030T090000\r\n";
+    let (set, warnings) = RRuleSet::from_str_lenient(str).unwrap();
+    let expected =
+        RRuleSet::from_str("DTSTART;TZID=America/New_York:20231023T090000\nRRULE:FREQ=WEEKLY;COUNT=3\nEXDATE:20231030T090000").unwrap();
+    assert_eq!(set.all(), expected.all());
+    assert_eq!(set.all().len(), 2);
+    assert_eq!(
+        warnings,
+        vec![
+            "\\r\\n line endings are converted to \\n",
+            "whitespace is removed: dtstart;tzid=America/New_York: 20231023T090000",
+            "whitespace is removed: exdate : 20231030T090000",
+            "property name freq is converted to uppercase",
+        ]
+    );
+    assert!(RRuleSet::from_str_lenient("DTSTART:2023\nRRULE:FREQ=DAILY").is_err());
+
+    let json_str = r#"{
+        "dtStart": "DTSTART:20231023T090000Z",
+        "freq": "RRULE:FREQ=DAILY",
+        "count": "3",
+        "byHour": ["9", 18],
+        "exDate": ["20231024T090000"]
+    }"#;
+    let (set, warnings) = RRuleSet::from_json_lenient(json_str).unwrap();
+    assert_eq!(
+        set.all(),
+        RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;COUNT=3;BYHOUR=9,18\nEXDATE:20231024T090000")
+            .unwrap()
+            .all()
+    );
+    assert_eq!(warnings.len(), 2);
+}
+
+#[test]
+fn test_other_props() {
+    let str = "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=3;X-NAME=foo;UNKNOWN=1,2\nX-WR-TIMEZONE:Asia/Shanghai\nEXDATE:20231024T180000Z";
+    let set = RRuleSet::from_str(str).unwrap();
+    assert_eq!(
+        set.rrule[0].other_props,
+        vec![
+            ("X-NAME".to_string(), "foo".to_string()),
+            ("UNKNOWN".to_string(), "1,2".to_string())
+        ]
+    );
+    assert_eq!(set.other_lines, vec!["X-WR-TIMEZONE:Asia/Shanghai"]);
+    assert_eq!(set.all().len(), 2);
+    assert_eq!(
+        set.to_string(),
+        "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=3;WKST=SU;X-NAME=foo;UNKNOWN=1,2\nEXDATE:20231024T180000Z\nX-WR-TIMEZONE:Asia/Shanghai"
+    );
+    assert_eq!(RRuleSet::from_str(&set.to_string()).unwrap().to_string(), set.to_string());
+
+    // 值中带=的扩展属性
+    let set = RRuleSet::from_str("DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;X-FOO=a=b").unwrap();
+    assert_eq!(set.rrule[0].other_props, vec![("X-FOO".to_string(), "a=b".to_string())]);
+    assert_eq!(set.to_string(), "DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;WKST=SU;X-FOO=a=b");
+
+    // 没有RRULE和RDATE时也保留DTSTART和其他行
+    let no_rule = "DTSTART:20231023T180000Z\nX-FOO:bar";
+    assert_eq!(RRuleSet::from_str(no_rule).unwrap().to_string(), no_rule);
+
+    // 严格模式只允许X-开头的扩展属性
+    assert!(RRuleSet::from_str_strict("DTSTART:20231023T180000Z\nRRULE:FREQ=DAILY;COUNT=3;X-NAME=foo").is_ok());
+    assert_eq!(
+        RRuleSet::from_str_strict(str).err(),
+        Some(RRuleError::InvalidProperty("UNKNOWN".to_string()))
+    );
+}
+
+#[test]
+fn test_multi_line_set() {
+    let str = "EXDATE;TZID=Asia/Shanghai:20231025T090000\r\nRRULE:FREQ=DAILY;COUNT=3;BYHOUR=9\r\nRDATE;TZID=Asia/Shanghai:20231101T090000\r\nDTSTART;TZID=Asia/Shanghai:20231023T090000\r\nRRULE:FREQ=WEEKLY;COUNT=2;BY\r\n DAY=SA\r\nEXRULE:FREQ=DAILY;COUNT=1\r\nRDATE;TZID=Asia/Shanghai:20231102T090000,\r\n\t20231103T090000\r\nEXDATE;TZID=Asia/Shanghai:20231103T090000";
+    let set = RRuleSet::from_str(str).unwrap();
+    assert_eq!(set.rrule.len(), 2);
+    assert_eq!(set.exrule.len(), 1);
+    assert_eq!(
+        set.all(),
+        to_times(vec!["20231024T090000", "20231028T090000", "20231101T090000", "20231102T090000", "20231104T090000"], Tz::Asia__Shanghai)
+    );
+
+    assert_eq!(
+        RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;COUNT=3\nDTSTART:20231024T090000Z").err(),
+        Some(RRuleError::InvalidRule("DTSTART occurs more than once".to_string()))
+    );
+}
+
+#[test]
+fn test_dst_policy() {
+    let to_utc = |set: &RRuleSet| {
+        set.all()
+            .iter()
+            .map(|n| PointTime::from_naive_date_time(&n.naive_utc()).with_form(TimeForm::Utc).to_string())
+            .collect::<Vec<_>>()
+    };
+    // 3月12日02:30不存在，11月5日01:30出现两次
+    let mut set = RRuleSet::from_str("DTSTART;TZID=America/New_York:20230311T023000\nRRULE:FREQ=DAILY;COUNT=3").unwrap();
+    assert_eq!(to_utc(&set), vec!["20230311T073000Z", "20230312T073000Z", "20230313T063000Z"]);
+    assert_eq!(set.all()[1].to_string(), "2023-03-12 03:30:00 EDT");
+    set.set_dst_policy(DstPolicy {
+        nonexistent: NonexistentTime::Skip,
+        ambiguous: AmbiguousTime::Skip,
+    });
+    assert_eq!(to_utc(&set), vec!["20230311T073000Z", "20230313T063000Z"]);
+
+    let mut set = RRuleSet::from_str("DTSTART;TZID=America/New_York:20231104T013000\nRRULE:FREQ=DAILY;COUNT=3").unwrap();
+    assert_eq!(to_utc(&set), vec!["20231104T053000Z", "20231105T053000Z", "20231106T063000Z"]);
+    set.set_dst_policy(DstPolicy {
+        ambiguous: AmbiguousTime::Latest,
+        ..Default::default()
+    });
+    assert_eq!(to_utc(&set), vec!["20231104T053000Z", "20231105T063000Z", "20231106T063000Z"]);
+    set.set_dst_policy(DstPolicy {
+        ambiguous: AmbiguousTime::Skip,
+        ..Default::default()
+    });
+    assert_eq!(to_utc(&set), vec!["20231104T053000Z", "20231106T063000Z"]);
+
+    // 02:00、02:30后移后与03:00、03:30重复，只返回一次
+    let set = RRuleSet::from_str("DTSTART;TZID=America/New_York:20230312T010000\nRRULE:FREQ=MINUTELY;INTERVAL=30;COUNT=6").unwrap();
+    assert_eq!(to_utc(&set), vec!["20230312T060000Z", "20230312T063000Z", "20230312T070000Z", "20230312T073000Z"]);
+}
+
+#[test]
+fn test_all_day() {
+    let date = |s: &str| NaiveDate::parse_from_str(s, "%Y%m%d").unwrap();
+    let set = RRuleSet::from_str("DTSTART;VALUE=DATE:20231115\nRRULE:FREQ=YEARLY;UNTIL=20251115").unwrap();
+    assert!(set.is_all_day());
+    assert_eq!(set.start_point_time.as_ref().unwrap().form, TimeForm::Date);
+    assert_eq!(set.all_dates(), vec![date("20231115"), date("20241115"), date("20251115")]);
+    assert_eq!(set.to_string(), "DTSTART;VALUE=DATE:20231115\nRRULE:FREQ=YEARLY;UNTIL=20251115;WKST=SU");
+
+    // 日期在时区下为当天的0点，TZID不会输出
+    let set = RRuleSet::from_str(
+        "DTSTART;TZID=Asia/Shanghai;VALUE=DATE:20231115\nRRULE:FREQ=WEEKLY;COUNT=3\nEXDATE;VALUE=DATE:20231122\nRDATE;VALUE=DATE:20231201,20231202",
+    )
+    .unwrap();
+    assert_eq!(set.tz, Tz::Asia__Shanghai);
+    assert_eq!(
+        set.all_dates(),
+        vec![date("20231115"), date("20231129"), date("20231201"), date("20231202")]
+    );
+    assert_eq!(
+        set.all()[0],
+        "20231115T000000".parse::<PointTime>().unwrap().with_timezone(&Timezone::Tz(Tz::Asia__Shanghai))
+    );
+    assert_eq!(
+        set.to_string(),
+        "DTSTART;VALUE=DATE:20231115\nRRULE:FREQ=WEEKLY;COUNT=3;WKST=SU\nRDATE;VALUE=DATE:20231201,20231202\nEXDATE;VALUE=DATE:20231122"
+    );
+
+    // 日期写法的UNTIL包含当天的所有时间
+    let set = RRuleSet::from_str("DTSTART:20231115T180000Z\nRRULE:FREQ=HOURLY;INTERVAL=2;UNTIL=20231115").unwrap();
+    assert!(!set.is_all_day());
+    assert_eq!(set.all().len(), 3);
+    let set = RRuleSet::from_str("DTSTART:20231115T180000Z\nRRULE:FREQ=DAILY;COUNT=2\nRDATE;VALUE=DATE:20231120").unwrap();
+    assert!(set.to_string().ends_with("\nRDATE;VALUE=DATE:20231120"));
+
+    let mut set = RRuleSet::from_str("RRULE:FREQ=MONTHLY;COUNT=2").unwrap();
+    set.set_dt_start("20231131").unwrap_err();
+    set.set_dt_start("20231130").unwrap();
+    assert_eq!(set.all_dates(), vec![date("20231130"), date("20231230")]);
+    assert!(RRuleSet::from_str("DTSTART;VALUE=DATE:20231115T000000").is_err());
+}
+
+#[test]
+fn test_time_form() {
+    // UTC和浮动时间的DTSTART按原样输出
+    let set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;COUNT=2").unwrap();
+    assert_eq!(set.start_point_time.as_ref().unwrap().form, TimeForm::Utc);
+    assert!(set.to_string().starts_with("DTSTART:20231023T090000Z\n"));
+    let set = RRuleSet::from_str("DTSTART:20231023T090000\nRRULE:FREQ=DAILY;COUNT=2").unwrap();
+    assert_eq!(set.start_point_time.as_ref().unwrap().form, TimeForm::Floating);
+    assert!(set.to_string().starts_with("DTSTART:20231023T090000\n"));
+
+    // DTSTART有时区时，UNTIL按UTC时间比较，20231024T030000Z是纽约的23点
+    let set = RRuleSet::from_str(
+        "DTSTART;TZID=America/New_York:20231023T220000\nRRULE:FREQ=HOURLY;UNTIL=20231024T030000Z",
+    )
+    .unwrap();
+    assert_eq!(
+        set.all(),
+        ["20231023T220000", "20231023T230000"]
+            .iter()
+            .map(|n| n.parse::<PointTime>().unwrap().with_timezone(&Timezone::Tz(Tz::America__New_York)))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        set.to_string(),
+        "DTSTART;TZID=America/New_York:20231023T220000\nRRULE:FREQ=HOURLY;UNTIL=20231024T030000Z;WKST=SU"
+    );
+
+    // 不带Z的UNTIL按当地时间处理，输出时转换为UTC时间
+    let set = RRuleSet::from_str("DTSTART;TZID=Asia/Shanghai:20231023T090000\nRRULE:FREQ=DAILY;UNTIL=20231025T090000").unwrap();
+    assert_eq!(set.all().len(), 3);
+    assert!(set.to_string().contains("UNTIL=20231025T010000Z"));
+    assert_eq!(RRuleSet::from_str(&set.to_string()).unwrap().all(), set.all());
+
+    // 不带TZID的RDATE、EXDATE带Z时是UTC时间，转换到DTSTART的时区下
+    let set = RRuleSet::from_str(
+        "DTSTART;TZID=Asia/Shanghai:20231023T090000\nRRULE:FREQ=DAILY;COUNT=3\nRDATE:20231101T010000Z\nEXDATE:20231024T010000Z,20231025T090000",
+    )
+    .unwrap();
+    assert_eq!(set.rdate, vec!["20231101T090000".parse::<PointTime>().unwrap()]);
+    assert_eq!(
+        set.to_string(),
+        "DTSTART;TZID=Asia/Shanghai:20231023T090000\nRRULE:FREQ=DAILY;COUNT=3;WKST=SU\nRDATE;TZID=Asia/Shanghai:20231101T090000\nEXDATE;TZID=Asia/Shanghai:20231024T090000,20231025T090000"
+    );
+    assert_eq!(set.all().len(), 2);
+
+    // tz()把已有的时间作为新时区下的当地时间
+    let mut set = RRuleSet::from_str("DTSTART:20231023T090000Z\nRRULE:FREQ=DAILY;COUNT=1").unwrap();
+    set.tz("Asia/Shanghai").unwrap();
+    assert_eq!(set.start_point_time.as_ref().unwrap().form, TimeForm::Zoned);
+    assert!(set.to_string().starts_with("DTSTART;TZID=Asia/Shanghai:20231023T090000\n"));
+}
+
 #[test]
 fn test_set_tz_in_str() {
     let str =  "DTSTART;TZID=America/New_York:20231013T003000\nRRULE:FREQ=WEEKLY;WKST=SU;INTERVAL=1;BYDAY=FR;UNTIL=20231128T105959";
     let mut set = RRuleSet::from_str(str).unwrap();
     assert_eq!(set.tz, Tz::America__New_York);
 
-    set.tz("America/Maceio");
+    set.tz("America/Maceio").unwrap();
     assert_eq!(set.tz, Tz::America__Maceio);
 }
 #[test]
@@ -164,7 +941,7 @@ fn test_set_until_in_str() {
     let mut set = RRuleSet::from_str(str).unwrap();
     assert_eq!(set.tz, Tz::America__New_York);
 
-    set.set_until("20231129T105959");
+    set.set_until("20231129T105959").unwrap();
     let rrule = set.rrule.get(0).unwrap();
     assert_eq!(
         rrule.until.as_ref().unwrap(),
@@ -176,9 +953,9 @@ fn test_set_between() {
     let str =  "DTSTART;TZID=America/New_York:20231013T091800\nRRULE:FREQ=WEEKLY;WKST=SU;INTERVAL=1;BYDAY=FR;UNTIL=20231128T105959";
     let mut set = RRuleSet::from_str(str).unwrap();
 
-    set.set_until("20231129T105959");
-    set.between("20231101T000000", "20231120T000000");
-    let list: Vec<chrono::prelude::DateTime<Tz>> = set.all();
+    set.set_until("20231129T105959").unwrap();
+    set.set_between("20231101T000000", "20231120T000000").unwrap();
+    let list: Vec<chrono::prelude::DateTime<Timezone>> = set.all().list;
     assert_eq!(
         list,
         vec!["20231103T091800", "20231110T091800", "20231117T091800"]
@@ -189,7 +966,7 @@ fn test_set_between() {
                 .with_timezone(&Tz::America__New_York))
             .collect::<Vec<_>>()
     );
-    set.between("20231129T000000", "20231220T000000");
+    set.set_between("20231129T000000", "20231220T000000").unwrap();
     assert!(set.all().is_empty());
 }
 
@@ -230,8 +1007,36 @@ fn test_expand_by_year() {
         ),
         (
             "DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=30;WKST=MO;BYDAY=MO;BYWEEKNO=3;UNTIL=20260112T091700",
+            vec!["20240115T091800", "20250113T091800"],
+        ),
+        (
+            "DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=30;WKST=MO;BYDAY=MO;BYWEEKNO=3;UNTIL=20260112T091800",
             vec!["20240115T091800", "20250113T091800", "20260112T091800"],
         ),
+        // 2026年有53个ISO周，2025年的最后一个周一属于2026年的第1周
+        (
+            "DTSTART:20250101T091800Z\nRRULE:FREQ=YEARLY;COUNT=2;BYDAY=MO;BYSETPOS=-1",
+            vec!["20251229T091800", "20261228T091800"],
+        ),
+        (
+            "DTSTART:20231123T091800Z\nRRULE:FREQ=YEARLY;COUNT=3;BYMONTH=12;BYMONTHDAY=25",
+            vec!["20231225T091800", "20241225T091800", "20251225T091800"],
+        ),
     ];
     run_test_by_vec(test_vec);
-}
\ No newline at end of file
+}
+#[test]
+fn test_huge_interval() {
+    // 超大的INTERVAL超出范围时结束，不会溢出panic或者倒退
+    for freq in ["YEARLY", "MONTHLY", "WEEKLY", "DAILY", "HOURLY", "MINUTELY", "SECONDLY"] {
+        for interval in ["2147483648", "4294967295"] {
+            let str = format!("DTSTART:20231123T091800Z\nRRULE:FREQ={};COUNT=3;INTERVAL={}", freq, interval);
+            let list = RRuleSet::from_str(&str).unwrap().all();
+            assert!(!list.is_empty() && list.windows(2).all(|w| w[0] < w[1]), "{}", str);
+            assert_eq!(
+                list[0],
+                "20231123T091800".parse::<PointTime>().unwrap().with_timezone(&Tz::UTC)
+            );
+        }
+    }
+}